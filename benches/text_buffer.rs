@@ -0,0 +1,62 @@
+//! Benchmarks for edits on large buffers.
+//!
+//! Sill is a binary crate, so we pull the buffer module in directly; it has no dependencies on
+//! the rest of the app.
+
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+
+#[path = "../src/text_buffer.rs"]
+#[allow(dead_code, unused_imports)]
+mod text_buffer;
+
+use text_buffer::{Replace, TextBuffer};
+
+/// Roughly 50MB of text, in lines of a typical log-file length.
+const LINES: usize = 640_000;
+
+fn large_buffer() -> TextBuffer {
+    let line = "2022-05-01T12:00:00Z INFO some_module: a fairly typical log line!";
+    let mut text = String::with_capacity(LINES * (line.len() + 1));
+    for _ in 0..LINES {
+        text.push_str(line);
+        text.push('\n');
+    }
+    TextBuffer::from_string(&text)
+}
+
+fn bench_replace(c: &mut Criterion) {
+    let mut buffer = large_buffer();
+    let mut group = c.benchmark_group("replace");
+    for (name, row) in [("start", 0), ("middle", LINES / 2), ("end", LINES - 1)] {
+        group.bench_with_input(BenchmarkId::new("write", name), &row, |b, row| {
+            b.iter(|| {
+                let undo = buffer.replace(Replace::write((*row, 10), 'x'));
+                black_box(buffer.replace(undo));
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("newline", name), &row, |b, row| {
+            b.iter(|| {
+                let insert = Replace::splice((*row, 10), TextBuffer::from_string("\n"));
+                let undo = buffer.replace(insert);
+                black_box(buffer.replace(undo));
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("delete_lines", name), &row, |b, row| {
+            b.iter(|| {
+                let undo = buffer.replace(Replace::remove((*row, 0), (*row + 1000, 0)));
+                black_box(buffer.replace(undo));
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_copy(c: &mut Criterion) {
+    let buffer = large_buffer();
+    c.bench_function("copy/half", |b| {
+        b.iter(|| black_box(buffer.copy((LINES / 4, 3), (LINES * 3 / 4, 3))))
+    });
+}
+
+criterion_group!(benches, bench_replace, bench_copy);
+criterion_main!(benches);
@@ -0,0 +1,203 @@
+//! A headless benchmark for the character recognizer, so changes to the templates or the
+//! recognizer itself can be measured instead of guessed at. Run it as `sill bench corpus.jsonl`;
+//! it doesn't touch the framebuffer, so it works on a desktop too.
+
+use crate::hwr::{ink_to_points, scale_ink, Sample, TemplateFile, TextStuff};
+use crate::{Config, Metrics};
+use anyhow::Context;
+use armrest::ink::Ink;
+use clap::{value_parser, Arg, ArgMatches, Command};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+pub fn command() -> Command<'static> {
+    Command::new("bench")
+        .about("Measure how well the templates recognize a labelled corpus of handwriting")
+        .arg(
+            Arg::new("corpus")
+                .help("A JSON Lines file of samples, like {\"char\": \"a\", \"ink\": ..., \"height\": 40}")
+                .required(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("templates")
+                .long("templates")
+                .help("The template file to test; defaults to the built-in templates")
+                .takes_value(true)
+                .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            Arg::new("cell-height")
+                .long("cell-height")
+                .help("The height of a grid cell, in pixels")
+                .takes_value(true)
+                .default_value("40")
+                .value_parser(value_parser!(i32)),
+        )
+}
+
+pub fn run(args: &ArgMatches) -> anyhow::Result<()> {
+    let height = *args.get_one::<i32>("cell-height").expect("defaulted");
+    let metrics = Metrics::new(height.clamp(20, 80));
+
+    let template_file: TemplateFile = match args.get_one::<PathBuf>("templates") {
+        Some(path) => serde_json::from_reader(BufReader::new(
+            File::open(path).with_context(|| format!("opening {}", path.display()))?,
+        ))?,
+        None => TemplateFile::default(),
+    };
+    let mut text_stuff = TextStuff::new();
+    text_stuff.load_from_file(template_file, &metrics, &Config::default());
+
+    let corpus = args.get_one::<PathBuf>("corpus").expect("required");
+    let reader = BufReader::new(
+        File::open(corpus).with_context(|| format!("opening {}", corpus.display()))?,
+    );
+    let mut report = Report::default();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let sample: Sample = serde_json::from_str(&line)
+            .with_context(|| format!("{}:{}", corpus.display(), i + 1))?;
+        let mut ink = Ink::from_string(&sample.ink);
+        if sample.height != metrics.height {
+            ink = scale_ink(&ink, metrics.height as f32 / sample.height as f32);
+        }
+
+        let start = Instant::now();
+        let points = ink_to_points(&ink, &metrics);
+        let recognized = text_stuff.char_recognizer.best_match(&points, f32::MAX);
+        report.record(sample.char, recognized, start.elapsed());
+    }
+
+    print!("{report}");
+    Ok(())
+}
+
+/// How the recognizer did on each sample.
+#[derive(Default)]
+struct Report {
+    /// For each expected char, how often it was recognized as each char. (Or as nothing, if there
+    /// were no templates to match against.)
+    confusion: BTreeMap<char, BTreeMap<Option<char>, usize>>,
+    latencies: Vec<Duration>,
+}
+
+impl Report {
+    fn record(&mut self, expected: char, recognized: Option<char>, latency: Duration) {
+        *self
+            .confusion
+            .entry(expected)
+            .or_default()
+            .entry(recognized)
+            .or_default() += 1;
+        self.latencies.push(latency);
+    }
+
+    /// The number of samples and the number recognized correctly, for the given char.
+    fn score(&self, expected: char) -> (usize, usize) {
+        let row = &self.confusion[&expected];
+        let total = row.values().sum();
+        let correct = row.get(&Some(expected)).copied().unwrap_or(0);
+        (total, correct)
+    }
+}
+
+fn percentile(sorted: &[Duration], percent: usize) -> Duration {
+    let index = (sorted.len() * percent / 100).min(sorted.len() - 1);
+    sorted[index]
+}
+
+fn percent(part: usize, total: usize) -> f32 {
+    100.0 * part as f32 / total.max(1) as f32
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.latencies.is_empty() {
+            return writeln!(f, "No samples.");
+        }
+
+        let (total, correct) = self
+            .confusion
+            .keys()
+            .map(|c| self.score(*c))
+            .fold((0, 0), |(t, c), (total, correct)| (t + total, c + correct));
+        writeln!(
+            f,
+            "accuracy: {correct}/{total} ({:.1}%)",
+            percent(correct, total)
+        )?;
+
+        let mut sorted = self.latencies.clone();
+        sorted.sort();
+        let mean = sorted.iter().sum::<Duration>() / sorted.len() as u32;
+        writeln!(
+            f,
+            "latency: mean {mean:.2?}, p50 {:.2?}, p95 {:.2?}, max {:.2?}",
+            percentile(&sorted, 50),
+            percentile(&sorted, 95),
+            sorted[sorted.len() - 1],
+        )?;
+
+        writeln!(f, "\nchar\tcorrect\tsamples\taccuracy")?;
+        for &c in self.confusion.keys() {
+            let (total, correct) = self.score(c);
+            writeln!(
+                f,
+                "{c:?}\t{correct}\t{total}\t{:.1}%",
+                percent(correct, total)
+            )?;
+        }
+
+        // The full matrix is mostly empty, so just list the cells off the diagonal.
+        let mut confusions: Vec<(char, Option<char>, usize)> = self
+            .confusion
+            .iter()
+            .flat_map(|(&expected, row)| {
+                row.iter()
+                    .filter(move |(recognized, _)| **recognized != Some(expected))
+                    .map(move |(&recognized, &count)| (expected, recognized, count))
+            })
+            .collect();
+        confusions.sort_by_key(|&(_, _, count)| Reverse(count));
+        writeln!(f, "\nexpected\trecognized\tcount")?;
+        for (expected, recognized, count) in confusions {
+            match recognized {
+                Some(recognized) => writeln!(f, "{expected:?}\t{recognized:?}\t{count}")?,
+                None => writeln!(f, "{expected:?}\tnothing\t{count}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_report() {
+        let mut report = Report::default();
+        let ms = Duration::from_millis;
+        report.record('l', Some('l'), ms(1));
+        report.record('l', Some('1'), ms(3));
+        report.record('l', Some('1'), ms(2));
+        report.record('o', Some('o'), ms(4));
+        assert_eq!(report.score('l'), (3, 1));
+        assert_eq!(report.score('o'), (1, 1));
+
+        let printed = report.to_string();
+        assert!(printed.starts_with("accuracy: 2/4 (50.0%)\n"));
+        assert!(printed.contains("p50 3.00ms"));
+        assert!(printed.contains("'l'\t1\t3\t33.3%\n"));
+        assert!(printed.contains("'l'\t'1'\t2\n"));
+    }
+}
@@ -1,11 +1,22 @@
+use crate::save::Backup;
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
-#[derive(Debug, Clone, Serialize, Deserialize, Eq, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 #[serde(default)]
 pub struct Config {
     pub cell_height: i32,
     pub extra_chars: Vec<String>,
     pub experimental: bool,
+    pub undo_limit: usize,
+    pub autosave_secs: u64,
+    pub backup: Backup,
+    pub restore_session: bool,
+    pub language_model: bool,
+    pub language_model_order: usize,
+    pub language_model_weight: f32,
+    pub language_model_corpus: Vec<PathBuf>,
+    pub record_samples: bool,
 }
 
 impl Config {
@@ -34,6 +45,15 @@ impl Default for Config {
             cell_height: 40,
             extra_chars: vec![],
             experimental: false,
+            undo_limit: 64,
+            autosave_secs: 30,
+            backup: Backup::None,
+            restore_session: true,
+            language_model: false,
+            language_model_order: 4,
+            language_model_weight: 0.5,
+            language_model_corpus: vec![],
+            record_samples: false,
         }
     }
 }
@@ -0,0 +1,181 @@
+use std::fmt::Write;
+
+/// Lines of context to show around each change, as in `diff -u`.
+const CONTEXT: usize = 3;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Op {
+    Same,
+    Delete,
+    Insert,
+}
+
+/// Find a shortest sequence of edits that turns `old` into `new`, using Myers' algorithm.
+/// This takes time proportional to the length of the inputs times the number of edits, so we
+/// give up if there are more than `max_edits` edits.
+pub fn diff<T: PartialEq>(old: &[T], new: &[T], max_edits: usize) -> Option<Vec<Op>> {
+    let (n, m) = (old.len() as isize, new.len() as isize);
+    let max = (old.len() + new.len()).min(max_edits) as isize;
+    let index = |k: isize| (k + max + 1) as usize;
+
+    // `v[k]` is the furthest x reached so far on diagonal k, where k = x - y. We keep the
+    // state before each round around, so we can retrace our steps at the end.
+    let mut v = vec![0isize; max as usize * 2 + 3];
+    let mut trace = vec![];
+    for d in 0..=max {
+        trace.push(v.clone());
+        for k in (-d..=d).step_by(2) {
+            let mut x = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+                v[index(k + 1)]
+            } else {
+                v[index(k - 1)] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && old[x as usize] == new[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[index(k)] = x;
+            if x >= n && y >= m {
+                return Some(backtrack(&trace, n, m, index));
+            }
+        }
+    }
+    None
+}
+
+fn backtrack(trace: &[Vec<isize>], n: isize, m: isize, index: impl Fn(isize) -> usize) -> Vec<Op> {
+    let mut ops = vec![];
+    let (mut x, mut y) = (n, m);
+    for (d, v) in trace.iter().enumerate().rev() {
+        let d = d as isize;
+        let k = x - y;
+        let prev_k = if k == -d || (k != d && v[index(k - 1)] < v[index(k + 1)]) {
+            k + 1
+        } else {
+            k - 1
+        };
+        let prev_x = v[index(prev_k)];
+        let prev_y = prev_x - prev_k;
+        while x > prev_x && y > prev_y {
+            ops.push(Op::Same);
+            x -= 1;
+            y -= 1;
+        }
+        if d > 0 {
+            ops.push(if x == prev_x { Op::Insert } else { Op::Delete });
+        }
+        x = prev_x;
+        y = prev_y;
+    }
+    ops.reverse();
+    ops
+}
+
+/// Describe the differences between two lists of lines in the style of `diff -u`, or `None`
+/// if there are more than `max_edits` differences.
+pub fn unified(
+    old_name: &str,
+    new_name: &str,
+    old: &[&str],
+    new: &[&str],
+    max_edits: usize,
+) -> Option<String> {
+    let ops = diff(old, new, max_edits)?;
+    let mut result = format!("--- {old_name}\n+++ {new_name}\n");
+
+    // The position in the old and new lines before each op.
+    let mut positions = Vec::with_capacity(ops.len() + 1);
+    let (mut x, mut y) = (0, 0);
+    for op in &ops {
+        positions.push((x, y));
+        match op {
+            Op::Same => {
+                x += 1;
+                y += 1;
+            }
+            Op::Delete => x += 1,
+            Op::Insert => y += 1,
+        }
+    }
+    positions.push((x, y));
+
+    let next_change = |from: usize| {
+        ops[from..]
+            .iter()
+            .position(|op| *op != Op::Same)
+            .map(|i| i + from)
+    };
+
+    let mut i = 0;
+    while let Some(start) = next_change(i) {
+        // Extend the hunk over any changes separated by a little context.
+        let mut end = start + 1;
+        while let Some(next) = next_change(end) {
+            if next - end > 2 * CONTEXT {
+                break;
+            }
+            end = next + 1;
+        }
+        let hunk_start = start.saturating_sub(CONTEXT).max(i);
+        let hunk_end = (end + CONTEXT).min(ops.len());
+
+        let (old_start, new_start) = positions[hunk_start];
+        let (old_end, new_end) = positions[hunk_end];
+        let range = |start: usize, end: usize| {
+            let len = end - start;
+            // Like diff, an empty range refers to the line before it.
+            let start = if len == 0 { start } else { start + 1 };
+            format!("{start},{len}")
+        };
+        let _ = writeln!(
+            result,
+            "@@ -{} +{} @@",
+            range(old_start, old_end),
+            range(new_start, new_end)
+        );
+        for (op, (x, y)) in ops[hunk_start..hunk_end]
+            .iter()
+            .zip(&positions[hunk_start..])
+        {
+            let _ = match op {
+                Op::Same => writeln!(result, " {}", old[*x]),
+                Op::Delete => writeln!(result, "-{}", old[*x]),
+                Op::Insert => writeln!(result, "+{}", new[*y]),
+            };
+        }
+        i = hunk_end;
+    }
+
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let old: Vec<char> = "ABCABBA".chars().collect();
+        let new: Vec<char> = "CBABAC".chars().collect();
+        let ops = diff(&old, &new, 100).unwrap();
+        let edits = ops.iter().filter(|op| **op != Op::Same).count();
+        assert_eq!(edits, 5);
+        assert_eq!(diff(&old, &new, 4), None);
+        assert_eq!(diff::<char>(&[], &[], 0), Some(vec![]));
+    }
+
+    #[test]
+    fn test_unified() {
+        let old: Vec<String> = (1..=20).map(|i| i.to_string()).collect();
+        let mut new = old.clone();
+        new[1] = "two".to_string();
+        new.remove(15);
+        let old: Vec<&str> = old.iter().map(|s| s.as_str()).collect();
+        let new: Vec<&str> = new.iter().map(|s| s.as_str()).collect();
+        let expected = "--- a\n+++ b\n\
+            @@ -1,5 +1,5 @@\n 1\n-2\n+two\n 3\n 4\n 5\n\
+            @@ -13,7 +13,6 @@\n 13\n 14\n 15\n-16\n 17\n 18\n 19\n";
+        assert_eq!(unified("a", "b", &old, &new, 100).unwrap(), expected);
+    }
+}
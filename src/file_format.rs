@@ -0,0 +1,418 @@
+use crate::TextBuffer;
+use serde::{Deserialize, Serialize};
+use std::io;
+
+/// Bytes that can't be decoded are mapped to chars in this range, at the very end of the last
+/// private-use plane, so they can be shown in the grid and written back out unchanged.
+const RAW_BYTE_BASE: u32 = 0x10FF00;
+
+fn raw_char(byte: u8) -> char {
+    char::from_u32(RAW_BYTE_BASE + byte as u32).expect("raw byte chars are valid")
+}
+
+/// If this char stands in for an undecodable byte, return the original byte.
+pub fn raw_byte(c: char) -> Option<u8> {
+    (c as u32)
+        .checked_sub(RAW_BYTE_BASE)
+        .and_then(|b| u8::try_from(b).ok())
+}
+
+/// Push a char that was decoded from the given bytes. In the unlikely event that the file
+/// genuinely contains a char from our raw-byte range, we store the bytes instead, so the char
+/// round-trips no matter what encoding we use to save.
+fn push_decoded(result: &mut String, c: char, bytes: &[u8]) {
+    if raw_byte(c).is_some() {
+        result.extend(bytes.iter().copied().map(raw_char));
+    } else {
+        result.push(c);
+    }
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum Encoding {
+    Utf8,
+    Latin1,
+    Utf16Le,
+    Utf16Be,
+}
+
+impl Encoding {
+    pub fn name(self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Latin1 => "Latin-1",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+        }
+    }
+
+    pub fn next(self) -> Encoding {
+        match self {
+            Encoding::Utf8 => Encoding::Latin1,
+            Encoding::Latin1 => Encoding::Utf16Le,
+            Encoding::Utf16Le => Encoding::Utf16Be,
+            Encoding::Utf16Be => Encoding::Utf8,
+        }
+    }
+
+    /// The byte-order mark for this encoding, or empty if the encoding doesn't have one.
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Latin1 => &[],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+        }
+    }
+
+    /// Guess the encoding of some file contents.
+    pub fn detect(bytes: &[u8]) -> Encoding {
+        for encoding in [Encoding::Utf8, Encoding::Utf16Le, Encoding::Utf16Be] {
+            if bytes.starts_with(encoding.bom()) {
+                return encoding;
+            }
+        }
+
+        // Mostly-ASCII UTF-16 has a zero in every other byte. Check this first: NUL is valid
+        // UTF-8, so that kind of UTF-16 would pass as UTF-8 too.
+        let zeros_at = |parity: usize| {
+            bytes
+                .iter()
+                .skip(parity)
+                .step_by(2)
+                .filter(|b| **b == 0)
+                .count()
+        };
+        if bytes.len() % 2 == 0 {
+            if zeros_at(1) > bytes.len() / 4 {
+                return Encoding::Utf16Le;
+            }
+            if zeros_at(0) > bytes.len() / 4 {
+                return Encoding::Utf16Be;
+            }
+        }
+
+        if std::str::from_utf8(bytes).is_ok() {
+            return Encoding::Utf8;
+        }
+
+        // If the non-ASCII bytes never form a valid UTF-8 sequence, it's probably a legacy
+        // single-byte encoding; otherwise it's more likely UTF-8 with a few stray bytes.
+        let as_utf8 = Encoding::Utf8.decode(bytes);
+        if as_utf8
+            .chars()
+            .any(|c| !c.is_ascii() && raw_byte(c).is_none())
+        {
+            Encoding::Utf8
+        } else {
+            Encoding::Latin1
+        }
+    }
+
+    fn decode(self, bytes: &[u8]) -> String {
+        let mut result = String::with_capacity(bytes.len());
+        match self {
+            Encoding::Utf8 => {
+                let mut rest = bytes;
+                while !rest.is_empty() {
+                    let (valid, invalid_len) = match std::str::from_utf8(rest) {
+                        Ok(valid) => (valid, 0),
+                        Err(e) => {
+                            let valid = std::str::from_utf8(&rest[..e.valid_up_to()])
+                                .expect("checked valid up to here");
+                            let invalid_len = e.error_len().unwrap_or(rest.len() - e.valid_up_to());
+                            (valid, invalid_len)
+                        }
+                    };
+                    for c in valid.chars() {
+                        push_decoded(&mut result, c, c.encode_utf8(&mut [0; 4]).as_bytes());
+                    }
+                    let invalid = &rest[valid.len()..(valid.len() + invalid_len)];
+                    result.extend(invalid.iter().copied().map(raw_char));
+                    rest = &rest[(valid.len() + invalid_len)..];
+                }
+            }
+            Encoding::Latin1 => {
+                result.extend(bytes.iter().map(|b| *b as char));
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                let units = bytes.chunks_exact(2).map(|pair| {
+                    let pair = [pair[0], pair[1]];
+                    if self == Encoding::Utf16Le {
+                        u16::from_le_bytes(pair)
+                    } else {
+                        u16::from_be_bytes(pair)
+                    }
+                });
+                for decoded in char::decode_utf16(units) {
+                    match decoded {
+                        Ok(c) => {
+                            let mut units = [0; 2];
+                            let mut bytes = vec![];
+                            for unit in c.encode_utf16(&mut units) {
+                                self.push_unit(&mut bytes, *unit);
+                            }
+                            push_decoded(&mut result, c, &bytes);
+                        }
+                        Err(e) => {
+                            let mut bytes = vec![];
+                            self.push_unit(&mut bytes, e.unpaired_surrogate());
+                            result.extend(bytes.into_iter().map(raw_char));
+                        }
+                    }
+                }
+                if bytes.len() % 2 == 1 {
+                    result.push(raw_char(bytes[bytes.len() - 1]));
+                }
+            }
+        }
+        result
+    }
+
+    fn push_unit(self, bytes: &mut Vec<u8>, unit: u16) {
+        if self == Encoding::Utf16Le {
+            bytes.extend(unit.to_le_bytes());
+        } else {
+            bytes.extend(unit.to_be_bytes());
+        }
+    }
+
+    fn encode(self, c: char, bytes: &mut Vec<u8>) -> io::Result<()> {
+        if let Some(byte) = raw_byte(c) {
+            bytes.push(byte);
+            return Ok(());
+        }
+        match self {
+            Encoding::Utf8 => {
+                bytes.extend(c.encode_utf8(&mut [0; 4]).as_bytes());
+            }
+            Encoding::Latin1 => {
+                let byte = u8::try_from(c as u32).map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::InvalidData,
+                        format!("`{c}` can't be written as {}", self.name()),
+                    )
+                })?;
+                bytes.push(byte);
+            }
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                for unit in c.encode_utf16(&mut [0; 2]) {
+                    self.push_unit(bytes, *unit);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// How lines are terminated in a file.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+
+    pub fn toggle(self) -> LineEnding {
+        match self {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        }
+    }
+}
+
+/// The details of a file's on-disk representation that we don't want to show in the grid.
+/// We detect these when a file is opened, and restore them when it's written back out.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct FileFormat {
+    pub encoding: Encoding,
+    pub line_ending: LineEnding,
+    pub bom: bool,
+}
+
+impl Default for FileFormat {
+    fn default() -> Self {
+        FileFormat {
+            encoding: Encoding::Utf8,
+            line_ending: LineEnding::Lf,
+            bom: false,
+        }
+    }
+}
+
+impl FileFormat {
+    /// Detect the format of some file contents, and decode the remaining text into a buffer.
+    pub fn decode(bytes: &[u8]) -> (FileFormat, TextBuffer) {
+        FileFormat::decode_as(Encoding::detect(bytes), bytes)
+    }
+
+    /// Decode some file contents with a particular encoding, detecting the rest of the format.
+    ///
+    /// Files with a mix of line endings are treated as LF, leaving any carriage returns in the
+    /// text; normalizing them would mean the file couldn't be written back unchanged.
+    pub fn decode_as(encoding: Encoding, bytes: &[u8]) -> (FileFormat, TextBuffer) {
+        let bom_bytes = encoding.bom();
+        let bom = !bom_bytes.is_empty() && bytes.starts_with(bom_bytes);
+        let text = if bom {
+            encoding.decode(&bytes[bom_bytes.len()..])
+        } else {
+            encoding.decode(bytes)
+        };
+
+        let newlines = text.matches('\n').count();
+        let line_ending = if newlines > 0 && text.matches("\r\n").count() == newlines {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        };
+
+        let buffer = match line_ending {
+            LineEnding::Lf => TextBuffer::from_string(&text),
+            LineEnding::CrLf => TextBuffer::from_string(&text.replace("\r\n", "\n")),
+        };
+
+        let format = FileFormat {
+            encoding,
+            line_ending,
+            bom,
+        };
+        (format, buffer)
+    }
+
+    /// Render the buffer as the bytes of a file in this format. This fails if the buffer has
+    /// chars that can't be represented in the file's encoding.
+    pub fn encode(&self, buffer: &TextBuffer) -> io::Result<Vec<u8>> {
+        let mut result = vec![];
+        if self.bom {
+            result.extend(self.encoding.bom());
+        }
+        let line_count = buffer.line_count();
+        for (i, line) in buffer.lines().enumerate() {
+            let last = i + 1 == line_count;
+            // A file with mixed line endings keeps its stray `\r`s as text; don't double them up
+            // when it's converted to CRLF.
+            let line = match self.line_ending {
+                LineEnding::CrLf if !last => line.strip_suffix(&['\r']).unwrap_or(line),
+                _ => line,
+            };
+            for c in line {
+                self.encoding.encode(*c, &mut result)?;
+            }
+            if !last {
+                for c in self.line_ending.as_str().chars() {
+                    self.encoding.encode(c, &mut result)?;
+                }
+            }
+        }
+        Ok(result)
+    }
+}
+
+/// Whether the buffer ends with a newline. This is just an empty last line in the buffer, so it
+/// round-trips without any special handling; but it's easy to miss in the grid.
+pub fn has_final_newline(buffer: &TextBuffer) -> bool {
+    buffer.line_count() > 1 && buffer.end().1 == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(bytes: impl AsRef<[u8]>) -> (FileFormat, TextBuffer) {
+        let bytes = bytes.as_ref();
+        let (format, buffer) = FileFormat::decode(bytes);
+        assert_eq!(format.encode(&buffer).unwrap(), bytes);
+        (format, buffer)
+    }
+
+    #[test]
+    fn test_crlf() {
+        let (format, buffer) = round_trip("one\r\ntwo\r\n");
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert_eq!(buffer.content_string(), "one\ntwo\n");
+        assert!(has_final_newline(&buffer));
+    }
+
+    #[test]
+    fn test_mixed_line_endings() {
+        let (format, buffer) = round_trip("one\r\ntwo\nthree");
+        assert_eq!(format.line_ending, LineEnding::Lf);
+        assert_eq!(buffer.line(0), Some(&['o', 'n', 'e', '\r'][..]));
+        assert!(!has_final_newline(&buffer));
+
+        let crlf = FileFormat {
+            line_ending: LineEnding::CrLf,
+            ..format
+        };
+        assert_eq!(crlf.encode(&buffer).unwrap(), b"one\r\ntwo\r\nthree");
+    }
+
+    #[test]
+    fn test_bom() {
+        let (format, buffer) = round_trip("\u{FEFF}text\n");
+        assert!(format.bom);
+        assert_eq!(buffer.content_string(), "text\n");
+    }
+
+    #[test]
+    fn test_latin1() {
+        let (format, buffer) = round_trip(b"caf\xe9\n");
+        assert_eq!(format.encoding, Encoding::Latin1);
+        assert_eq!(buffer.content_string(), "café\n");
+    }
+
+    #[test]
+    fn test_stray_bytes() {
+        let (format, buffer) = round_trip(b"na\xc3\xafve \xff\xfe\xc3\n");
+        assert_eq!(format.encoding, Encoding::Utf8);
+        let line = buffer.line(0).unwrap();
+        assert_eq!(line[..4], ['n', 'a', 'ï', 'v']);
+        let raw: Vec<_> = line.iter().filter_map(|c| raw_byte(*c)).collect();
+        assert_eq!(raw, [0xff, 0xfe, 0xc3]);
+
+        // Chars that happen to overlap with our raw-byte range survive too.
+        round_trip("\u{10FF41}".as_bytes());
+    }
+
+    #[test]
+    fn test_utf16() {
+        let (format, buffer) = round_trip(b"\xff\xfeh\x00i\x00\r\x00\n\x00");
+        assert_eq!(format.encoding, Encoding::Utf16Le);
+        assert_eq!(format.line_ending, LineEnding::CrLf);
+        assert!(format.bom);
+        assert_eq!(buffer.content_string(), "hi\n");
+
+        let (format, buffer) = round_trip(b"\x00h\x00i\xd8\x00");
+        assert_eq!(format.encoding, Encoding::Utf16Be);
+        assert_eq!(raw_byte(buffer.line(0).unwrap()[2]), Some(0xd8));
+
+        // Plain ASCII in UTF-16 is valid UTF-8 too, but that's not what it's meant to be.
+        let (format, buffer) = round_trip(b"h\x00i\x00");
+        assert_eq!(format.encoding, Encoding::Utf16Le);
+        assert!(!format.bom);
+        assert_eq!(buffer.content_string(), "hi");
+    }
+
+    #[test]
+    fn test_unencodable() {
+        let format = FileFormat {
+            encoding: Encoding::Latin1,
+            ..FileFormat::default()
+        };
+        assert!(format.encode(&TextBuffer::from_string("€")).is_err());
+    }
+}
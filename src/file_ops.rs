@@ -0,0 +1,116 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// An operation that takes a source path and a destination; the source is picked from the
+/// Paths list, and the destination written into the path window.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum FileOp {
+    Rename,
+    Duplicate,
+}
+
+impl FileOp {
+    pub fn name(self) -> &'static str {
+        match self {
+            FileOp::Rename => "rename",
+            FileOp::Duplicate => "duplicate",
+        }
+    }
+
+    pub fn apply(self, from: &Path, to: &Path) -> io::Result<()> {
+        // Neither rename nor copy promise not to clobber an existing file.
+        if to.symlink_metadata().is_ok() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{} already exists", to.display()),
+            ));
+        }
+        match self {
+            FileOp::Rename => fs::rename(from, to),
+            FileOp::Duplicate => copy_recursive(from, to),
+        }
+    }
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> io::Result<()> {
+    if from.is_dir() {
+        fs::create_dir(to)?;
+        fs::set_permissions(to, fs::metadata(from)?.permissions())?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+        Ok(())
+    } else {
+        fs::copy(from, to).map(|_| ())
+    }
+}
+
+/// Delete a file, or a directory if it's empty. (Deleting a whole tree by accident is too easy
+/// to do with a stray tap; there's always the shell for that.)
+pub fn delete(path: &Path) -> io::Result<()> {
+    if path.symlink_metadata()?.is_dir() {
+        fs::remove_dir(path)
+    } else {
+        fs::remove_file(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh, empty directory to play in.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sill-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_rename() {
+        let dir = scratch_dir("rename");
+        let (a, b) = (dir.join("a"), dir.join("b"));
+        fs::write(&a, "a").unwrap();
+        fs::write(&b, "b").unwrap();
+        // Never clobber an existing file.
+        assert!(FileOp::Rename.apply(&a, &b).is_err());
+        assert_eq!(fs::read_to_string(&b).unwrap(), "b");
+
+        fs::remove_file(&b).unwrap();
+        FileOp::Rename.apply(&a, &b).unwrap();
+        assert!(!a.exists());
+        assert_eq!(fs::read_to_string(&b).unwrap(), "a");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_duplicate() {
+        let dir = scratch_dir("duplicate");
+        let (from, to) = (dir.join("from"), dir.join("to"));
+        fs::create_dir_all(from.join("sub")).unwrap();
+        fs::write(from.join("sub/file"), "contents").unwrap();
+        FileOp::Duplicate.apply(&from, &to).unwrap();
+        assert_eq!(fs::read_to_string(to.join("sub/file")).unwrap(), "contents");
+        assert!(from.join("sub/file").exists());
+        assert!(FileOp::Duplicate.apply(&from, &to).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_delete() {
+        let dir = scratch_dir("delete");
+        let sub = dir.join("sub");
+        fs::create_dir(&sub).unwrap();
+        fs::write(sub.join("file"), "").unwrap();
+        // Only empty directories.
+        assert!(delete(&sub).is_err());
+        delete(&sub.join("file")).unwrap();
+        delete(&sub).unwrap();
+        assert!(!sub.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
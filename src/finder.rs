@@ -0,0 +1,295 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Don't descend further than this below the root of the index.
+const MAX_DEPTH: usize = 8;
+/// Stop walking once we've found this many files, so a huge tree can't hang the UI.
+const MAX_FILES: usize = 20_000;
+/// Rebuild the index after this long, to pick up files that were created since.
+const MAX_INDEX_AGE: Duration = Duration::from_secs(120);
+/// Filesystems made up by the kernel. They're huge, full of files that aren't really files, and
+/// never what anyone's looking for; walking them from `/` would just hang the UI.
+const VIRTUAL_DIRS: [&str; 3] = ["/proc", "/sys", "/dev"];
+
+/// A single line from a `.gitignore` file. We support the common subset of the syntax:
+/// `*`, `?` and `**` wildcards, negation with `!`, anchoring with `/`, and directory-only
+/// patterns with a trailing `/`.
+struct IgnorePattern {
+    glob: Vec<char>,
+    negated: bool,
+    dir_only: bool,
+    /// Anchored patterns match the whole path relative to the `.gitignore`; others just the name.
+    anchored: bool,
+}
+
+impl IgnorePattern {
+    fn parse(line: &str) -> Option<IgnorePattern> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let line = line.strip_prefix('/').unwrap_or(line);
+        Some(IgnorePattern {
+            glob: line.chars().collect(),
+            negated,
+            dir_only,
+            anchored,
+        })
+    }
+
+    fn matches(&self, relative_path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let text = if self.anchored {
+            relative_path
+        } else {
+            relative_path.rsplit('/').next().unwrap_or(relative_path)
+        };
+        let text: Vec<char> = text.chars().collect();
+        glob_match(&self.glob, &text)
+    }
+}
+
+fn glob_match(glob: &[char], text: &[char]) -> bool {
+    match glob {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => {
+            // Zero or more whole directories.
+            glob_match(rest, text)
+                || (0..text.len()).any(|i| text[i] == '/' && glob_match(rest, &text[i + 1..]))
+        }
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match(rest, &text[i..])),
+        ['*', rest @ ..] => (0..=text.len())
+            .take_while(|i| *i == 0 || text[i - 1] != '/')
+            .any(|i| glob_match(rest, &text[i..])),
+        ['?', rest @ ..] => match text {
+            [c, text_rest @ ..] if *c != '/' => glob_match(rest, text_rest),
+            _ => false,
+        },
+        [g, rest @ ..] => match text {
+            [c, text_rest @ ..] if c == g => glob_match(rest, text_rest),
+            _ => false,
+        },
+    }
+}
+
+/// The patterns from one `.gitignore` file, which apply to paths under its directory.
+struct IgnoreFile {
+    /// The directory of the `.gitignore`, relative to the root of the index, with a trailing `/`.
+    base: String,
+    patterns: Vec<IgnorePattern>,
+}
+
+/// Whether a path is ignored. Later patterns, and patterns in deeper files, take precedence.
+fn is_ignored(ignores: &[IgnoreFile], relative_path: &str, is_dir: bool) -> bool {
+    for ignore in ignores.iter().rev() {
+        let path = match relative_path.strip_prefix(&ignore.base) {
+            Some(path) => path,
+            None => continue,
+        };
+        if let Some(pattern) = ignore
+            .patterns
+            .iter()
+            .rev()
+            .find(|p| p.matches(path, is_dir))
+        {
+            return !pattern.negated;
+        }
+    }
+    false
+}
+
+/// Score how well a query matches a path, or `None` if it doesn't match at all. The query's
+/// chars must appear in order, but not necessarily next to each other; matches that are
+/// consecutive, at the start of a word, or in the file name itself score higher.
+fn score(query: &[char], path: &str) -> Option<i64> {
+    let chars: Vec<char> = path.chars().collect();
+    let name_start = chars.iter().rposition(|c| *c == '/').map_or(0, |i| i + 1);
+    let mut remaining = query.iter().peekable();
+    let mut score = 0;
+    let mut previous_match = None;
+    for (i, c) in chars.iter().enumerate() {
+        let wanted = match remaining.peek() {
+            Some(wanted) => **wanted,
+            None => break,
+        };
+        if !c.to_lowercase().eq(wanted.to_lowercase()) {
+            continue;
+        }
+        remaining.next();
+        score += 1;
+        if i > 0 && previous_match == Some(i - 1) {
+            score += 4;
+        }
+        if i == 0 || matches!(chars[i - 1], '/' | '_' | '-' | '.' | ' ') {
+            score += 6;
+        }
+        if i >= name_start {
+            score += 2;
+        }
+        previous_match = Some(i);
+    }
+    if remaining.peek().is_some() {
+        return None;
+    }
+    // Among equally good matches, prefer shorter paths.
+    Some(score * 1000 - chars.len() as i64)
+}
+
+/// Whether the entry is an ordinary file, or a link to one. Devices, pipes and sockets are left
+/// out: reading them can block, or never finish.
+fn is_regular_file(entry: &fs::DirEntry) -> bool {
+    match entry.file_type() {
+        Ok(t) if t.is_symlink() => fs::metadata(entry.path()).is_ok_and(|m| m.is_file()),
+        Ok(t) => t.is_file(),
+        Err(_) => false,
+    }
+}
+
+/// A cached list of the files under a directory, for fuzzy-finding.
+#[derive(Clone)]
+pub struct FileIndex {
+    root: PathBuf,
+    built: Instant,
+    /// Relative to the root.
+    files: Vec<String>,
+}
+
+impl FileIndex {
+    pub fn build(root: &Path) -> FileIndex {
+        let mut index = FileIndex {
+            root: root.to_path_buf(),
+            built: Instant::now(),
+            files: vec![],
+        };
+        index.walk(root, String::new(), 0, &mut vec![]);
+        index
+    }
+
+    fn walk(&mut self, dir: &Path, relative: String, depth: usize, ignores: &mut Vec<IgnoreFile>) {
+        let has_ignore_file = match fs::read_to_string(dir.join(".gitignore")) {
+            Ok(contents) => {
+                ignores.push(IgnoreFile {
+                    base: relative.clone(),
+                    patterns: contents.lines().filter_map(IgnorePattern::parse).collect(),
+                });
+                true
+            }
+            Err(_) => false,
+        };
+
+        let mut entries: Vec<_> = match fs::read_dir(dir) {
+            Ok(read) => read.filter_map(|e| e.ok()).collect(),
+            Err(_) => vec![],
+        };
+        entries.sort_by_key(|e| e.file_name());
+
+        for entry in entries {
+            if self.files.len() >= MAX_FILES {
+                break;
+            }
+            let name = match entry.file_name().into_string() {
+                Ok(name) => name,
+                Err(_) => continue,
+            };
+            // Skip hidden files, including `.git` itself.
+            if name.starts_with('.') {
+                continue;
+            }
+            let is_dir = entry.file_type().is_ok_and(|t| t.is_dir());
+            let path = format!("{relative}{name}");
+            if is_ignored(ignores, &path, is_dir) {
+                continue;
+            }
+            if is_dir {
+                let full_path = entry.path();
+                if VIRTUAL_DIRS.iter().any(|d| full_path == Path::new(d)) {
+                    continue;
+                }
+                if depth + 1 < MAX_DEPTH {
+                    self.walk(&full_path, format!("{path}/"), depth + 1, ignores);
+                }
+            } else if is_regular_file(&entry) {
+                self.files.push(path);
+            }
+        }
+
+        if has_ignore_file {
+            ignores.pop();
+        }
+    }
+
+    /// Whether this index can answer queries about the given directory.
+    pub fn is_fresh(&self, root: &Path) -> bool {
+        self.root == root && self.built.elapsed() < MAX_INDEX_AGE
+    }
+
+    /// The best matches for a query, best first, as paths relative to the root.
+    pub fn search(&self, query: &str, limit: usize) -> Vec<&str> {
+        let query: Vec<char> = query.chars().collect();
+        let mut scored: Vec<(i64, &str)> = self
+            .files
+            .iter()
+            .filter_map(|f| Some((score(&query, f)?, f.as_str())))
+            .collect();
+        scored.sort_by(|(a, _), (b, _)| b.cmp(a));
+        scored.truncate(limit);
+        scored.into_iter().map(|(_, f)| f).collect()
+    }
+
+    /// Every file in the index, relative to the root.
+    pub fn files(&self) -> impl Iterator<Item = &str> {
+        self.files.iter().map(|f| f.as_str())
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ignored(gitignore: &str, path: &str, is_dir: bool) -> bool {
+        let ignores = [IgnoreFile {
+            base: String::new(),
+            patterns: gitignore.lines().filter_map(IgnorePattern::parse).collect(),
+        }];
+        is_ignored(&ignores, path, is_dir)
+    }
+
+    #[test]
+    fn test_gitignore() {
+        let gitignore = "# build output\n/target\n*.o\n!keep.o\nlogs/\ndocs/**/*.tmp\n";
+        assert!(ignored(gitignore, "target", true));
+        assert!(!ignored(gitignore, "src/target", true));
+        assert!(ignored(gitignore, "src/main.o", false));
+        assert!(!ignored(gitignore, "src/keep.o", false));
+        assert!(ignored(gitignore, "src/logs", true));
+        assert!(!ignored(gitignore, "src/logs", false));
+        assert!(ignored(gitignore, "docs/a.tmp", false));
+        assert!(ignored(gitignore, "docs/a/b/c.tmp", false));
+        assert!(!ignored(gitignore, "src/main.rs", false));
+    }
+
+    #[test]
+    fn test_score() {
+        let query: Vec<char> = "tw".chars().collect();
+        assert!(score(&query, "src/main.rs").is_none());
+        let word_starts = score(&query, "src/text_window.rs").unwrap();
+        let scattered = score(&query, "notes/tomorrow.md").unwrap();
+        assert!(word_starts > scattered);
+    }
+}
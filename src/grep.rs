@@ -0,0 +1,183 @@
+use crate::finder::FileIndex;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Lines of context to show around each match.
+const CONTEXT: usize = 1;
+/// Skip any file bigger than this; it's probably not source code.
+const MAX_FILE_SIZE: u64 = 1 << 20;
+/// Stop searching after this many matches, so a common query can't hang the UI.
+const MAX_MATCHES: usize = 1000;
+/// Long lines are cut short; nobody's going to read them on the results page anyway.
+const MAX_LINE_CHARS: usize = 200;
+/// Like git, we call a file binary if there's a NUL byte near the start.
+const BINARY_CHECK_BYTES: usize = 8000;
+
+pub struct GrepLine {
+    /// Zero-based, like `TextWindow::origin`.
+    pub number: usize,
+    pub text: String,
+    /// False for the context lines around a match.
+    pub is_match: bool,
+}
+
+pub struct FileMatches {
+    pub path: PathBuf,
+    pub lines: Vec<GrepLine>,
+}
+
+impl FileMatches {
+    pub fn match_count(&self) -> usize {
+        self.lines.iter().filter(|l| l.is_match).count()
+    }
+}
+
+/// One row of the results page: either a file heading, or a line in that file.
+pub enum GrepRow<'a> {
+    File(&'a FileMatches),
+    Line(&'a Path, &'a GrepLine),
+}
+
+#[derive(Default)]
+pub struct GrepResults {
+    pub root: PathBuf,
+    pub query: String,
+    pub files: Vec<FileMatches>,
+    /// Whether we gave up before searching every file.
+    pub truncated: bool,
+}
+
+impl GrepResults {
+    pub fn rows(&self) -> impl Iterator<Item = GrepRow<'_>> {
+        self.files.iter().flat_map(|file| {
+            std::iter::once(GrepRow::File(file)).chain(
+                file.lines
+                    .iter()
+                    .map(move |line| GrepRow::Line(&file.path, line)),
+            )
+        })
+    }
+
+    pub fn match_count(&self) -> usize {
+        self.files.iter().map(|f| f.match_count()).sum()
+    }
+}
+
+/// Search every file under the root for a literal string, skipping hidden, ignored, binary and
+/// very large files.
+pub fn grep(root: &Path, query: &str) -> GrepResults {
+    let mut results = GrepResults {
+        root: root.to_path_buf(),
+        query: query.to_string(),
+        ..GrepResults::default()
+    };
+    if query.is_empty() {
+        return results;
+    }
+
+    let index = FileIndex::build(root);
+    let mut remaining = MAX_MATCHES;
+    for relative in index.files() {
+        let path = root.join(relative);
+        let contents = match read_text(&path) {
+            Some(contents) => contents,
+            None => continue,
+        };
+        let lines = grep_text(&contents, query, remaining);
+        if lines.is_empty() {
+            continue;
+        }
+        let file = FileMatches { path, lines };
+        remaining -= file.match_count();
+        results.files.push(file);
+        if remaining == 0 {
+            results.truncated = true;
+            break;
+        }
+    }
+    results
+}
+
+/// The contents of a file, or `None` if it's too big, binary, unreadable, or not a regular file.
+pub fn read_text(path: &Path) -> Option<String> {
+    // Check before opening: opening a pipe blocks, and a device might never stop reading.
+    let metadata = fs::metadata(path).ok()?;
+    if !metadata.is_file() || metadata.len() > MAX_FILE_SIZE {
+        return None;
+    }
+    let mut file = fs::File::open(path).ok()?;
+    let mut bytes = vec![];
+    file.read_to_end(&mut bytes).ok()?;
+    if bytes[..bytes.len().min(BINARY_CHECK_BYTES)].contains(&0) {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&bytes).into_owned())
+}
+
+/// The lines of the text that contain the query, at most `limit` of them, along with a little
+/// context around each. Overlapping context is merged, as in `grep -C`.
+fn grep_text(text: &str, query: &str, limit: usize) -> Vec<GrepLine> {
+    let lines: Vec<&str> = text.lines().collect();
+    let matches: Vec<usize> = lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| line.contains(query))
+        .map(|(i, _)| i)
+        .take(limit)
+        .collect();
+
+    let mut result: Vec<GrepLine> = vec![];
+    for &m in &matches {
+        let start = m.saturating_sub(CONTEXT);
+        let end = (m + CONTEXT + 1).min(lines.len());
+        for (number, line) in lines.iter().enumerate().take(end).skip(start) {
+            if result.last().is_some_and(|l| l.number >= number) {
+                continue;
+            }
+            result.push(GrepLine {
+                number,
+                text: line.chars().take(MAX_LINE_CHARS).collect(),
+                is_match: matches.binary_search(&number).is_ok(),
+            });
+        }
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_grep_text() {
+        let text = "one\ntwo\nthree\nfour\nfive\nsix\nseven\n";
+        let found: Vec<(usize, bool)> = grep_text(text, "e", 100)
+            .iter()
+            .map(|l| (l.number, l.is_match))
+            .collect();
+        assert_eq!(
+            found,
+            [
+                (0, true),
+                (1, false),
+                (2, true),
+                (3, false),
+                (4, true),
+                (5, false),
+                (6, true)
+            ]
+        );
+
+        let limited: Vec<usize> = grep_text(text, "o", 1).iter().map(|l| l.number).collect();
+        assert_eq!(limited, [0, 1]);
+        assert!(grep_text(text, "eight", 100).is_empty());
+    }
+
+    #[test]
+    fn test_read_text() {
+        assert!(read_text(Path::new(file!())).is_some());
+        assert!(read_text(Path::new("/dev/zero")).is_none());
+        assert!(read_text(Path::new("/")).is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::undo_tree::UndoTree;
+use crate::util::{content_hash, write_atomic};
+use crate::{TextWindow, BASE_DIRS};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const HISTORY_DIR: &str = "history";
+
+/// The undo history of a file, as it was the last time the file was saved.
+///
+/// The history is only valid for the exact contents it was saved with, so we record a hash of
+/// the file alongside it, and ignore the history if the file has been changed since.
+#[derive(Serialize, Deserialize)]
+struct History {
+    path: PathBuf,
+    content_hash: u64,
+    undo_tree: UndoTree,
+}
+
+fn history_file(path: &Path) -> io::Result<PathBuf> {
+    let key = content_hash(path.as_os_str().to_string_lossy().as_bytes());
+    BASE_DIRS.place_data_file(Path::new(HISTORY_DIR).join(format!("{key:016x}.json")))
+}
+
+/// Save the undo history of a window, given the contents it was just saved with.
+pub fn save(path: &Path, contents: &[u8], window: &TextWindow) -> io::Result<()> {
+    let history = History {
+        path: path.to_path_buf(),
+        content_hash: content_hash(contents),
+        undo_tree: window.undo_tree.clone(),
+    };
+    let json = serde_json::to_vec(&history)?;
+    write_atomic(&history_file(path)?, &json)
+}
+
+/// Restore the undo history for a file into a window, if we have a history for those contents.
+pub fn restore(path: &Path, contents: &[u8], window: &mut TextWindow) -> io::Result<()> {
+    let file = match File::open(history_file(path)?) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(e),
+    };
+    let mut history: History = serde_json::from_reader(io::BufReader::new(file))?;
+    if history.path != path || history.content_hash != content_hash(contents) {
+        // Stale; it'll get replaced next time the file is saved.
+        return Ok(());
+    }
+
+    history.undo_tree.prune(window.undo_limit);
+    window.undo_tree = history.undo_tree;
+    Ok(())
+}
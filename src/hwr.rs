@@ -1,3 +1,4 @@
+use crate::lm::LanguageModel;
 use crate::{Config, Metrics, TextBuffer};
 use armrest::dollar::Points;
 use armrest::ink::Ink;
@@ -8,12 +9,24 @@ use serde::Serialize;
 use std::borrow::Cow;
 use std::cmp::Ordering;
 use std::collections::{BTreeMap, VecDeque};
+use std::fs::File;
+use std::io::Write;
 
 /// A set of characters that we always include in the template, even when not explicitly configured.
 /// Aside from being very common, this lets us use these characters in other places; eg. allowing
 /// the user to enter a character by writing out the code point.
 pub const PRINTABLE_ASCII: &str = "0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz.!\"#$%&'()*+,-/:;<=>?@[\\]^_`{|}~";
 
+/// The punctuation that turns up in ordinary file names and paths.
+const PATH_PUNCTUATION: &str = "/.-_~+,:@=%#&!'()[]";
+
+/// Chars that make sense in a path: letters and digits in any script, and common punctuation.
+/// Linux allows nearly anything, but symbols like `|`, `\` or `*` are rarely wanted in a file
+/// name, and are easily mistaken for chars that are.
+pub fn is_path_char(c: char) -> bool {
+    c.is_alphanumeric() || PATH_PUNCTUATION.contains(c)
+}
+
 /// Convert an ink to a point cloud.
 ///
 /// This differs from the suggested behaviour for $P, since it recenters and scales based on a
@@ -31,6 +44,18 @@ pub fn ink_to_points(ink: &Ink, metrics: &Metrics) -> Points {
     points
 }
 
+/// Scale an ink up or down, eg. to match a template recorded at a different cell height.
+pub fn scale_ink(original: &Ink, scale: f32) -> Ink {
+    let mut ink = Ink::new();
+    for stroke in original.strokes() {
+        for p in stroke {
+            ink.push(p.x * scale, p.y * scale, p.z);
+        }
+        ink.pen_up();
+    }
+    ink
+}
+
 pub fn default_char_height() -> i32 {
     40
 }
@@ -92,6 +117,20 @@ impl<'a> TemplateFile<'a> {
     }
 }
 
+/// One line of a sample file: some ink, and the char it was meant to be. Sill writes these when
+/// `record_samples` is set, and `sill bench` reads them.
+#[derive(Serialize, Deserialize)]
+pub struct Sample {
+    pub char: char,
+    pub ink: String,
+    /// The height of the cell the ink was written in, so it can be scaled to match the templates.
+    #[serde(default = "default_char_height")]
+    pub height: i32,
+    /// What the ink was first recognized as, if it was recorded while writing.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recognized: Option<char>,
+}
+
 /// Why both? We don't want to constantly lose precision reserializing.
 pub struct Template {
     pub ink: Ink,
@@ -153,6 +192,56 @@ impl CharRecognizer {
         }
     }
 
+    /// The best few distinct allowed chars for the query, best first, along with their scores.
+    /// As with `best_match`, lower scores are better.
+    pub fn candidates(
+        &mut self,
+        query: &Points,
+        count: usize,
+        allowed: impl Fn(char) -> bool,
+    ) -> Vec<(char, f32)> {
+        let mut best: Vec<(char, f32)> = vec![];
+        if count == 0 {
+            return best;
+        }
+
+        let mut best_index = None;
+        for (i, (template, &c)) in self.templates.iter().zip(&self.chars).enumerate() {
+            if !allowed(c) {
+                continue;
+            }
+            // Once the list is full, we can abandon any template that can't beat the last entry.
+            let cutoff = if best.len() < count {
+                f32::INFINITY
+            } else {
+                best[count - 1].1
+            };
+            let score = query.distance(template, cutoff);
+            if score >= cutoff {
+                continue;
+            }
+            if best.first().is_none_or(|(_, s)| score < *s) {
+                best_index = Some(i);
+            }
+            // Each char appears once, with the score of its closest template.
+            match best.iter().position(|(ch, _)| *ch == c) {
+                Some(j) if best[j].1 <= score => continue,
+                Some(j) => {
+                    best.remove(j);
+                }
+                None => {}
+            }
+            let at = best.partition_point(|(_, s)| *s <= score);
+            best.insert(at, (c, score));
+            best.truncate(count);
+        }
+
+        if let Some(index) = best_index {
+            self.promote(index);
+        }
+        best
+    }
+
     pub fn promote(&mut self, index: usize) {
         if index == 0 || index >= self.templates.len() {
             return;
@@ -163,13 +252,26 @@ impl CharRecognizer {
 }
 
 const NUM_CANDIDATES: usize = 64;
+/// Corrections only add templates for a char until it has this many; past that, more of them
+/// would mostly just slow recognition down.
+const MAX_TEMPLATES_PER_CHAR: usize = 16;
+
+pub struct Clipboard {
+    pub content: TextBuffer,
+    /// Cut or copied from a block selection, so it's pasted as a block too.
+    pub block: bool,
+}
 
 pub struct TextStuff {
     pub templates: Vec<CharTemplates>,
     pub char_recognizer: CharRecognizer,
     pub big_recognizer: CharRecognizer,
-    pub clipboard: Option<TextBuffer>,
+    pub clipboard: Option<Clipboard>,
     pub candidate_templates: VecDeque<(Template, Points, char)>,
+    /// Picks between similar-looking chars based on the surrounding text, if enabled.
+    pub language_model: Option<LanguageModel>,
+    /// Where to append samples of the user's writing, if they've opted in.
+    pub sample_log: Option<File>,
 }
 
 impl TextStuff {
@@ -180,6 +282,8 @@ impl TextStuff {
             big_recognizer: CharRecognizer::new([]),
             clipboard: None,
             candidate_templates: VecDeque::new(),
+            language_model: None,
+            sample_log: None,
         }
     }
 
@@ -204,17 +308,7 @@ impl TextStuff {
 
         let parse_template = |string: Cow<'_, str>| match scale {
             None => Template::from_string(string.into_owned()),
-            Some(scale) => {
-                let original = Ink::from_string(&string);
-                let mut ink = Ink::new();
-                for stroke in original.strokes() {
-                    for p in stroke {
-                        ink.push(p.x * scale, p.y * scale, p.z);
-                    }
-                    ink.pen_up();
-                }
-                Template::from_ink(ink)
-            }
+            Some(scale) => Template::from_ink(scale_ink(&Ink::from_string(&string), scale)),
         };
 
         let char_data = |ch: char, strings: Vec<Cow<str>>| CharTemplates {
@@ -303,6 +397,43 @@ impl TextStuff {
         }
     }
 
+    /// The user picked the right char from the correction strip, so we know exactly what this ink
+    /// was meant to be. Unlike overwrites, there's nothing to second-guess: it becomes a template
+    /// straight away.
+    pub fn on_correction(&mut self, ink: Ink, metrics: &Metrics, correct: char) {
+        if ink.len() <= 1 {
+            return;
+        }
+        let ct = self.templates.iter_mut().find(|ct| ct.char == correct);
+        if let Some(ct) = ct.filter(|ct| ct.templates.len() < MAX_TEMPLATES_PER_CHAR) {
+            self.char_recognizer
+                .templates
+                .push(ink_to_points(&ink, metrics));
+            self.char_recognizer.chars.push(correct);
+            ct.templates.push(Template::from_ink(ink));
+        }
+    }
+
+    /// Log some ink, along with what we recognized it as and what it turned out to be.
+    pub fn record_sample(&mut self, ink: &Ink, recognized: char, char: char, height: i32) {
+        let file = match &mut self.sample_log {
+            Some(file) => file,
+            None => return,
+        };
+        let sample = Sample {
+            char,
+            ink: ink.to_string(),
+            height,
+            recognized: Some(recognized),
+        };
+        let mut line = serde_json::to_string(&sample).expect("serializing a sample");
+        line.push('\n');
+        // One write per line, so a crash can't leave half a sample behind.
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            eprintln!("Unable to record sample: {e}");
+        }
+    }
+
     pub fn init_recognizer(&mut self, metrics: &Metrics) {
         // Discard trivial or invalid templates.
         for ct in &mut self.templates {
@@ -317,7 +448,7 @@ impl TextStuff {
         self.big_recognizer = CharRecognizer::new(
             self.templates
                 .iter()
-                .filter(|ct| ['X', 'C', 'V', 'S', '>', '<', 'Q', 'N', 'P'].contains(&ct.char))
+                .filter(|ct| ['X', 'C', 'V', 'S', '>', '<', 'Q', 'N', 'P', 'B'].contains(&ct.char))
                 .flat_map(|ct| {
                     let c = ct.char;
                     ct.templates
@@ -328,3 +459,72 @@ impl TextStuff {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn points(stroke: &[(f32, f32)]) -> Points {
+        let mut ink = Ink::new();
+        for (i, &(x, y)) in stroke.iter().enumerate() {
+            ink.push(x, y, i as f32 * 0.01);
+        }
+        ink.pen_up();
+        ink_to_points(&ink, &Metrics::new(40))
+    }
+
+    #[test]
+    fn test_candidates() {
+        let mut recognizer = CharRecognizer::new([
+            (points(&[(10.0, 20.0), (30.0, 20.0)]), '-'),
+            (points(&[(16.0, 12.0), (20.0, 5.0), (20.0, 35.0)]), '1'),
+            (points(&[(21.0, 5.0), (20.0, 35.0)]), 'l'),
+            (points(&[(20.0, 5.0), (20.0, 35.0)]), 'l'),
+        ]);
+        let query = points(&[(20.0, 6.0), (20.0, 34.0)]);
+
+        // Best first, with each char listed once, and no more than asked for.
+        let chars = |candidates: Vec<(char, f32)>| -> Vec<char> {
+            candidates.into_iter().map(|(c, _)| c).collect()
+        };
+        assert_eq!(
+            chars(recognizer.candidates(&query, 2, |_| true)),
+            ['l', '1']
+        );
+        assert_eq!(
+            chars(recognizer.candidates(&query, 5, |_| true)),
+            ['l', '1', '-']
+        );
+        assert!(recognizer.candidates(&query, 0, |_| true).is_empty());
+        // The best template moves to the front, so it's tried first next time.
+        assert_eq!(recognizer.chars[0], 'l');
+    }
+
+    #[test]
+    fn test_candidates_allowed() {
+        let mut recognizer = CharRecognizer::new([
+            (points(&[(10.0, 20.0), (30.0, 20.0)]), '-'),
+            (points(&[(21.0, 5.0), (20.0, 35.0)]), 'l'),
+            (points(&[(20.0, 5.0), (20.0, 35.0)]), '|'),
+        ]);
+        let query = points(&[(20.0, 5.0), (20.0, 35.0)]);
+        let best = |recognizer: &mut CharRecognizer, allowed: fn(char) -> bool| {
+            recognizer.candidates(&query, 2, allowed)[0].0
+        };
+        assert_eq!(best(&mut recognizer, |_| true), '|');
+        assert_eq!(best(&mut recognizer, is_path_char), 'l');
+        assert!(recognizer
+            .candidates(&query, 2, |c| c.is_ascii_digit())
+            .is_empty());
+    }
+
+    #[test]
+    fn test_is_path_char() {
+        for c in ['a', 'Z', '0', 'é', '/', '.', '-', '_', '~'] {
+            assert!(is_path_char(c), "{c:?}");
+        }
+        for c in ['|', '\\', '*', '?', '"', '\t', '\0'] {
+            assert!(!is_path_char(c), "{c:?}");
+        }
+    }
+}
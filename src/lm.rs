@@ -0,0 +1,223 @@
+use crate::finder::FileIndex;
+use crate::grep::read_text;
+use crate::grid_ui::Coord;
+use std::collections::HashMap;
+use std::io;
+use std::path::Path;
+
+/// Stands in for the cells before the start of a line, so the model can learn how lines begin.
+const LINE_START: char = '\n';
+/// How many of the likeliest partial sequences to keep while rescoring.
+const BEAM_WIDTH: usize = 8;
+/// Stop reading training files after this much text; more would just slow down startup.
+const MAX_TRAINING_BYTES: usize = 8 << 20;
+
+/// How often each char follows a particular context.
+#[derive(Default)]
+struct Followers {
+    total: u32,
+    counts: HashMap<char, u32>,
+}
+
+/// A character n-gram model, used to pick between the recognizer's guesses based on the
+/// surrounding text. Probabilities are smoothed with Witten-Bell interpolation, so unusual
+/// sequences are unlikely but never impossible.
+pub struct LanguageModel {
+    /// The number of chars in each n-gram, including the one being predicted.
+    order: usize,
+    /// How much the model's opinion counts for, relative to the recognizer's distances.
+    weight: f32,
+    /// Keyed by context, from the empty string up to `order - 1` chars long.
+    contexts: HashMap<String, Followers>,
+    trained_bytes: usize,
+}
+
+impl LanguageModel {
+    pub fn new(order: usize, weight: f32) -> LanguageModel {
+        LanguageModel {
+            order: order.max(1),
+            weight,
+            contexts: HashMap::new(),
+            trained_bytes: 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.contexts.is_empty()
+    }
+
+    pub fn train(&mut self, text: &str) {
+        self.trained_bytes += text.len();
+        for line in text.lines() {
+            let chars: Vec<char> = std::iter::once(LINE_START).chain(line.chars()).collect();
+            for i in 1..chars.len() {
+                for n in 0..self.order.min(i + 1) {
+                    let context: String = chars[i - n..i].iter().collect();
+                    let followers = self.contexts.entry(context).or_default();
+                    followers.total += 1;
+                    *followers.counts.entry(chars[i]).or_default() += 1;
+                }
+            }
+        }
+    }
+
+    /// Train on a file, or on every text file under a directory, skipping the same files that
+    /// grep does.
+    pub fn train_path(&mut self, path: &Path) -> io::Result<()> {
+        if !path.metadata()?.is_dir() {
+            if let Some(text) = read_text(path) {
+                self.train(&text);
+            }
+            return Ok(());
+        }
+        let index = FileIndex::build(path);
+        for relative in index.files() {
+            if self.trained_bytes >= MAX_TRAINING_BYTES {
+                break;
+            }
+            if let Some(text) = read_text(&path.join(relative)) {
+                self.train(&text);
+            }
+        }
+        Ok(())
+    }
+
+    /// The log probability of the char, given the chars that came before it.
+    pub fn log_prob(&self, context: &[char], c: char) -> f32 {
+        let context = &context[context.len().saturating_sub(self.order - 1)..];
+
+        // Start from the unigram distribution, with add-one smoothing for chars we've never seen.
+        let mut prob = match self.contexts.get("") {
+            Some(f) => {
+                let count = f.counts.get(&c).copied().unwrap_or(0) as f32;
+                (count + 1.0) / (f.total as f32 + f.counts.len() as f32 + 1.0)
+            }
+            None => return 0.0,
+        };
+        // Then mix in each longer context, trusting it more the more often we've seen it.
+        for n in 1..=context.len() {
+            let key: String = context[context.len() - n..].iter().collect();
+            let followers = match self.contexts.get(&key) {
+                Some(f) => f,
+                None => break,
+            };
+            let count = followers.counts.get(&c).copied().unwrap_or(0) as f32;
+            let types = followers.counts.len() as f32;
+            prob = (count + types * prob) / (followers.total as f32 + types);
+        }
+        prob.ln()
+    }
+
+    /// Pick a char for each written cell, given the recognizer's candidates and their distances.
+    /// Instead of taking the closest match in each cell, this finds the sequence that best
+    /// balances closeness against likeliness, taking the neighbouring text into account.
+    /// `text` returns the char currently at a coordinate, or `None` past the end of the line.
+    pub fn rescore(
+        &self,
+        cells: &[(Coord, &[(char, f32)])],
+        text: impl Fn(Coord) -> Option<char>,
+    ) -> Vec<char> {
+        // The char at a coordinate, if the given choices were made for the written cells.
+        let char_at = |chosen: &[char], coord: Coord| {
+            cells
+                .iter()
+                .zip(chosen)
+                .find(|((c, _), _)| *c == coord)
+                .map(|(_, ch)| *ch)
+                .or_else(|| text(coord))
+                .unwrap_or(' ')
+        };
+        // The chars before a coordinate, oldest first.
+        let context = |chosen: &[char], (row, col): Coord| {
+            let mut context = vec![];
+            for k in 1..self.order {
+                if k > col {
+                    context.push(LINE_START);
+                    break;
+                }
+                context.push(char_at(chosen, (row, col - k)));
+            }
+            context.reverse();
+            context
+        };
+
+        let mut beam: Vec<(f32, Vec<char>)> = vec![(0.0, vec![])];
+        for &(coord, candidates) in cells {
+            let mut next = vec![];
+            for (cost, chosen) in &beam {
+                let before = context(chosen, coord);
+                for &(c, distance) in candidates {
+                    let cost = cost + distance - self.weight * self.log_prob(&before, c);
+                    let mut chosen = chosen.clone();
+                    chosen.push(c);
+                    next.push((cost, chosen));
+                }
+            }
+            next.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+            next.truncate(BEAM_WIDTH);
+            beam = next;
+        }
+
+        // The written chars should also fit with whatever follows them.
+        for (cost, chosen) in &mut beam {
+            for &((row, col), _) in cells {
+                let after = (row, col + 1);
+                if cells.iter().any(|(c, _)| *c == after) {
+                    continue;
+                }
+                if let Some(c) = text(after) {
+                    *cost -= self.weight * self.log_prob(&context(chosen, after), c);
+                }
+            }
+        }
+
+        beam.into_iter()
+            .min_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, chosen)| chosen)
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_log_prob() {
+        let mut model = LanguageModel::new(3, 1.0);
+        model.train("hello world\nhold the line\n");
+        assert!(model.log_prob(&['h', 'e'], 'l') > model.log_prob(&['h', 'e'], '1'));
+        assert!(model.log_prob(&[LINE_START], 'h') > model.log_prob(&[LINE_START], 'o'));
+        // Never-seen chars are unlikely, but not impossible.
+        assert!(model.log_prob(&['x', 'y'], 'é').is_finite());
+    }
+
+    #[test]
+    fn test_rescore() {
+        let mut model = LanguageModel::new(3, 1.0);
+        model.train("hello world\nhold the line\nall is well\n");
+        let line: Vec<char> = "he lo".chars().collect();
+        let text = |(row, col): Coord| {
+            if row == 0 {
+                line.get(col).copied()
+            } else {
+                None
+            }
+        };
+
+        // Slightly closer to a `1`, but the `l` fits much better.
+        let candidates = [('1', 1.0), ('l', 1.1)];
+        assert_eq!(model.rescore(&[((0, 2), &candidates)], text), ['l']);
+
+        // Without the model, or when the recognizer is confident, the closest match wins.
+        let unweighted = LanguageModel::new(3, 0.0);
+        assert_eq!(unweighted.rescore(&[((0, 2), &candidates)], text), ['1']);
+        let confident = [('1', 1.0), ('l', 20.0)];
+        assert_eq!(model.rescore(&[((0, 2), &confident)], text), ['1']);
+
+        // Several cells are chosen together.
+        let o = [('0', 1.0), ('o', 1.1)];
+        let cells: [(Coord, &[(char, f32)]); 2] = [((1, 0), &o), ((1, 1), &candidates)];
+        assert_eq!(model.rescore(&cells, text), ['o', 'l']);
+    }
+}
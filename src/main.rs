@@ -3,9 +3,12 @@ use std::collections::{BTreeMap, VecDeque};
 use std::fmt::Display;
 use std::fs::File;
 use std::io::{ErrorKind, Read, Write};
+use std::ops::Range;
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::{Path, PathBuf};
 use std::process::{Child, Stdio};
 use std::rc::Rc;
+use std::time::{Duration, UNIX_EPOCH};
 use std::{env, fs, io, process, thread};
 
 use armrest::app;
@@ -17,24 +20,50 @@ use armrest::libremarkable::framebuffer::cgmath::Vector2;
 use armrest::libremarkable::framebuffer::common::{DISPLAYHEIGHT, DISPLAYWIDTH};
 use armrest::ui::{Side, Text, View, Widget};
 use once_cell::sync::Lazy;
+use regex::Regex;
 use xdg::BaseDirectories;
 
 use config::*;
+use file_format::*;
+use file_ops::FileOp;
+use finder::FileIndex;
 use font::*;
+use grep::{GrepResults, GrepRow};
 use grid_ui::*;
 use hwr::*;
 use ink_type::*;
+use lm::LanguageModel;
+use places::{Place, Places};
+use save::{Backup, FileStamp};
+use search::SearchOptions;
+use session::{Session, SessionTab};
+use swap::Swap;
 use text_buffer::*;
 use text_window::*;
+use util::{format_age, format_size, now};
 use widgets::*;
 
+mod bench;
 mod config;
+mod diff;
+mod file_format;
+mod file_ops;
+mod finder;
 mod font;
+mod grep;
 mod grid_ui;
+mod history;
 mod hwr;
 mod ink_type;
+mod lm;
+mod places;
+mod save;
+mod search;
+mod session;
+mod swap;
 mod text_buffer;
 mod text_window;
+mod undo_tree;
 mod util;
 mod widgets;
 
@@ -47,8 +76,11 @@ const SCREEN_WIDTH: i32 = DISPLAYWIDTH as i32;
 const TOP_MARGIN: i32 = 100;
 const LEFT_MARGIN: i32 = 100;
 const DEFAULT_CHAR_HEIGHT: i32 = 40;
+/// How many of the most recently recognized chars get alternatives in the footer.
+const CORRECTION_CELLS: usize = 3;
 
 const TEMPLATE_FILE: &str = "templates.json";
+const SAMPLES_FILE: &str = "samples.jsonl";
 const CONFIG_FILE: &str = "sill.toml";
 const BASH_RC_FILE: &str = "sill.bashrc";
 
@@ -59,39 +91,157 @@ static APP_NAME: Lazy<String> =
 
 #[derive(Clone)]
 pub enum Msg {
-    MetaPath { current_path: String },
-    SwitchTab { tab: Tab },
-    SearchResult(usize, usize),
-    Write { ink: Ink },
-    Erase { ink: Ink },
-    Swipe { towards: Side },
-    Open { path: PathBuf },
-    OpenShell { working_dir: PathBuf },
-    Tab { id: usize, msg: TabMsg },
+    MetaPath {
+        current_path: String,
+    },
+    SwitchTab {
+        tab: Tab,
+    },
+    /// Jump to a match in a text tab, selecting it.
+    SearchResult(usize, Coord, Coord),
+    SearchOptions(SearchOptions),
+    StartReplace,
+    Replace(ReplaceStep),
+    Write {
+        ink: Ink,
+    },
+    Erase {
+        ink: Ink,
+    },
+    WriteReplacement {
+        ink: Ink,
+    },
+    EraseReplacement {
+        ink: Ink,
+    },
+    Swipe {
+        towards: Side,
+    },
+    Open {
+        path: PathBuf,
+    },
+    OpenShell {
+        working_dir: PathBuf,
+    },
+    Tab {
+        id: usize,
+        msg: TabMsg,
+    },
     New,
+    Autosave,
+    Recover {
+        file: PathBuf,
+    },
+    DiscardSwap {
+        file: PathBuf,
+    },
+    DismissPrompt,
+    TogglePin {
+        path: PathBuf,
+    },
+    MarkPath {
+        path: PathBuf,
+        op: FileOp,
+    },
+    ApplyFileOp,
+    CancelFileOp,
+    ConfirmDelete {
+        path: PathBuf,
+    },
+    Delete {
+        path: PathBuf,
+    },
+    MakeDir,
+    ToggleHidden,
+    /// A file index finished building in the background.
+    Indexed {
+        index: FileIndex,
+    },
+    StartGrep {
+        root: PathBuf,
+    },
+    RunGrep,
+    OpenAt {
+        path: PathBuf,
+        line: usize,
+    },
 }
 
 #[derive(Clone)]
 pub enum TabMsg {
-    ShellInput { stderr: bool, content: String },
+    ShellInput {
+        stderr: bool,
+        content: String,
+    },
     SubmitShell,
-    SaveAs { path: PathBuf },
+    SaveAs {
+        path: PathBuf,
+    },
+    CycleEncoding,
+    ToggleLineEnding,
+    ToggleBom,
+    ToggleWrap,
+    /// Replace a recently-recognized char with one of the alternatives from the correction strip.
+    Correct {
+        coord: Coord,
+        char: char,
+    },
+    Reload,
+    KeepMine {
+        save: bool,
+        /// Close the tab once it's saved.
+        close: bool,
+    },
+    DiffDisk,
     Undo,
     Redo,
+    JumpHistory(usize),
+    /// Scroll so the given row is at the top of the screen, or as close as it can get.
+    ScrollTo {
+        row: usize,
+    },
+    /// Scroll to the row number written in the go-to window.
+    GotoLine,
     Save,
+    SaveAndClose,
+    /// Close the tab, unless that would lose something; then we ask first.
+    Close,
     Quit,
 }
 
 pub struct Meta {
     path_window: TextWindow,
-    suggested: Vec<String>,
+    suggested: Vec<PathEntry>,
+    show_hidden: bool,
+    /// A file picked from the Paths list, to be renamed or copied to the focus path.
+    pending: Option<(FileOp, PathBuf)>,
+    /// Fuzzy matches for the last part of the path, from anywhere under its directory.
+    found: Vec<PathBuf>,
+    index: Option<FileIndex>,
+    /// The directory being indexed in the background, if any. Walking a big tree can take a
+    /// while, so we keep showing what we have until it's done.
+    indexing: Option<PathBuf>,
+    sender: Sender<Msg>,
+    recoverable: Vec<(PathBuf, Swap)>,
+    places: Places,
 }
 
 impl Meta {
-    fn new(path_window: TextWindow) -> Meta {
+    fn new(path_window: TextWindow, sender: Sender<Msg>) -> Meta {
         let mut new = Meta {
             path_window,
             suggested: vec![],
+            show_hidden: false,
+            pending: None,
+            found: vec![],
+            index: None,
+            indexing: None,
+            sender,
+            recoverable: swap::orphans(),
+            places: Places::load().unwrap_or_else(|e| {
+                eprintln!("Unable to load recent and pinned files: {e}");
+                Places::default()
+            }),
         };
 
         new.reload_suggestions();
@@ -100,7 +250,46 @@ impl Meta {
     }
 
     pub fn reload_suggestions(&mut self) {
-        self.suggested = suggestions(&self.path_window.buffer.content_string()).unwrap_or_default()
+        let current_path = self.path_window.buffer.content_string();
+        self.suggested = suggestions(&current_path, self.show_hidden).unwrap_or_default();
+        self.found = self.find(&current_path);
+    }
+
+    /// Drop the file index, so it's rebuilt to match whatever just changed on disk.
+    fn forget_index(&mut self) {
+        self.index = None;
+        self.indexing = None;
+    }
+
+    fn find(&mut self, current_path: &str) -> Vec<PathBuf> {
+        let (dir, query) = match current_path.rsplit_once('/') {
+            Some((dir, query)) if current_path.starts_with('/') && !query.is_empty() => {
+                (if dir.is_empty() { "/" } else { dir }, query)
+            }
+            _ => return vec![],
+        };
+        let dir = Path::new(dir);
+        let fresh = self.index.as_ref().is_some_and(|index| index.is_fresh(dir));
+        if !fresh && self.indexing.as_deref() != Some(dir) {
+            self.indexing = Some(dir.to_path_buf());
+            let dir = dir.to_path_buf();
+            let sender = self.sender.clone();
+            thread::spawn(move || {
+                sender.send(Msg::Indexed {
+                    index: FileIndex::build(&dir),
+                });
+            });
+        }
+        // Until then, an old index of the same directory is better than nothing.
+        let index = match &self.index {
+            Some(index) if index.root() == dir => index,
+            _ => return vec![],
+        };
+        index
+            .search(query, NUM_FOUND)
+            .into_iter()
+            .map(|relative| index.root().join(relative))
+            .collect()
     }
 }
 
@@ -112,7 +301,19 @@ pub enum Tab {
     Search {
         id: usize,
         contents: IndexedString,
-        results: Vec<usize>,
+        /// Byte ranges in the contents.
+        results: Vec<Range<usize>>,
+        offset: usize,
+    },
+    History {
+        id: usize,
+        offset: usize,
+    },
+    Grep {
+        offset: usize,
+    },
+    Goto {
+        id: usize,
     },
 }
 
@@ -147,6 +348,19 @@ impl ShellTab {
         Ok(link)
     }
 
+    /// The name of a command the shell is running, if any.
+    pub fn running_job(&self) -> Option<String> {
+        let shell = self.child.id();
+        fs::read_dir("/proc")
+            .ok()?
+            .filter_map(|entry| entry.ok())
+            .find_map(|entry| {
+                let stat = fs::read_to_string(entry.path().join("stat")).ok()?;
+                let (name, ppid) = parse_stat(&stat)?;
+                (ppid == shell).then(|| name.to_string())
+            })
+    }
+
     pub fn set_title(&mut self) {
         if let Ok(dir) = self.working_dir() {
             let last = dir
@@ -252,10 +466,12 @@ impl ShellTab {
             true,
         );
 
+        let mut shell_output = TextWindow::new(TextBuffer::empty(), atlas, metrics, dimensions);
+        shell_output.record_samples = false;
         let mut tab = ShellTab {
             title: String::new(),
             child,
-            shell_output: TextWindow::new(TextBuffer::empty(), atlas, metrics, dimensions),
+            shell_output,
             history: Default::default(),
         };
         tab.set_title();
@@ -267,21 +483,234 @@ struct TextTab {
     title: String,
     path: Option<PathBuf>,
     text: TextWindow,
+    format: FileFormat,
     dirty: bool,
+    /// The state of the buffer as of the last swap file we wrote, if any.
+    swapped: Option<(usize, FileFormat)>,
+    /// We only back up the original contents of the file, not every intermediate save.
+    backed_up: bool,
+    /// The file as of when we last read or wrote it.
+    stamp: Option<FileStamp>,
 }
 
 impl TextTab {
-    fn save(&mut self) -> io::Result<()> {
+    fn save(&mut self, backup: Backup) -> io::Result<()> {
         if let Some(path) = &self.path {
-            let write_result = std::fs::write(path, self.text.buffer.content_string());
-            if write_result.is_ok() {
-                self.dirty = false;
-            }
-            write_result
+            let contents = self.format.encode(&self.text.buffer)?;
+            let backup = if self.backed_up { Backup::None } else { backup };
+            save::save_file(path, &contents, backup)?;
+            self.dirty = false;
+            self.backed_up = true;
+            self.stamp = FileStamp::new(path, &contents).ok();
+            // The file itself is safely saved by now, even if its history isn't.
+            history::save(path, &contents, &self.text)
         } else {
             Ok(())
         }
     }
+
+    /// Whether some other program has changed the file since we last read or wrote it.
+    fn changed_on_disk(&self) -> io::Result<bool> {
+        match (&self.path, &self.stamp) {
+            (Some(path), Some(stamp)) => stamp.changed(path),
+            _ => Ok(false),
+        }
+    }
+
+    /// Accept the file on disk as the new baseline, without changing the buffer.
+    fn restamp(&mut self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            self.stamp = Some(FileStamp::new(path, &fs::read(path)?)?);
+        }
+        Ok(())
+    }
+
+    /// Replace the buffer with the current contents of the file. Unlike reopening the file,
+    /// this can be undone.
+    fn reload(&mut self) -> io::Result<()> {
+        if let Some(path) = &self.path {
+            let bytes = fs::read(path)?;
+            let (format, buffer) = FileFormat::decode(&bytes);
+            let until = self.text.buffer.end();
+            self.text.replace(Replace {
+                from: (0, 0),
+                until,
+                content: buffer,
+            });
+            self.format = format;
+            self.dirty = false;
+            self.stamp = Some(FileStamp::new(path, &bytes)?);
+        }
+        Ok(())
+    }
+
+    /// Describe how the buffer differs from the file on disk.
+    fn diff_with_disk(&self) -> io::Result<String> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(String::new()),
+        };
+        let (_, disk) = FileFormat::decode(&fs::read(path)?);
+        let disk = disk.content_string();
+        let mine = self.text.buffer.content_string();
+        let disk_lines: Vec<&str> = disk.split('\n').collect();
+        let my_lines: Vec<&str> = mine.split('\n').collect();
+        let diff = diff::unified(
+            &format!("{} (on disk)", path.display()),
+            &format!("{} (unsaved)", path.display()),
+            &disk_lines,
+            &my_lines,
+            MAX_DIFF_EDITS,
+        );
+        Ok(diff.unwrap_or_else(|| format!("More than {MAX_DIFF_EDITS} lines differ.\n")))
+    }
+
+    /// Write a swap file if the buffer has changed since the last one, or remove the swap file
+    /// if the buffer has since been saved.
+    fn autosave(&mut self, id: usize) -> io::Result<()> {
+        if !self.dirty {
+            if self.swapped.take().is_some() {
+                swap::remove(id)?;
+            }
+            return Ok(());
+        }
+
+        let state = (self.text.undo_tree.current(), self.format);
+        if self.swapped != Some(state) {
+            let snapshot = Swap::new(
+                self.title.clone(),
+                self.path.clone(),
+                self.format,
+                self.text.buffer.clone(),
+                self.text.undo_tree.clone(),
+            );
+            swap::write(id, &snapshot)?;
+            self.swapped = Some(state);
+        }
+        Ok(())
+    }
+}
+
+/// What to do with the current match, while stepping through a search and replace.
+#[derive(Clone, Copy)]
+pub enum ReplaceStep {
+    Replace,
+    Skip,
+    All,
+    Done,
+}
+
+/// A search and replace in progress, stepping through the matches in a text tab.
+struct Replacing {
+    id: usize,
+    matcher: Regex,
+    replacement: String,
+    options: SearchOptions,
+    /// The start of the current match; or, before it's been found, where to start looking.
+    from: Coord,
+    replaced: usize,
+}
+
+/// A question for the user about one of the tabs, shown in the footer until it's answered.
+struct Prompt {
+    tab: Option<usize>,
+    message: String,
+    choices: Vec<(&'static str, Msg)>,
+}
+
+impl Prompt {
+    fn changed_on_disk(id: usize, title: &str, saving: bool, closing: bool) -> Prompt {
+        let choice = |label, msg| (label, Msg::Tab { id, msg });
+        Prompt {
+            tab: Some(id),
+            message: format!("{title} was changed on disk. "),
+            choices: vec![
+                choice("reload", TabMsg::Reload),
+                choice(
+                    "keep mine",
+                    TabMsg::KeepMine {
+                        save: saving,
+                        close: closing,
+                    },
+                ),
+                choice("diff", TabMsg::DiffDisk),
+            ],
+        }
+    }
+
+    fn unsaved(id: usize, text_tab: &TextTab) -> Prompt {
+        let mut choices = vec![];
+        // Without a path, "save" would quietly do nothing and lose the changes anyway.
+        if text_tab.path.is_some() {
+            choices.push((
+                "save",
+                Msg::Tab {
+                    id,
+                    msg: TabMsg::SaveAndClose,
+                },
+            ));
+        }
+        choices.push((
+            "discard",
+            Msg::Tab {
+                id,
+                msg: TabMsg::Quit,
+            },
+        ));
+        choices.push(("cancel", Msg::DismissPrompt));
+        Prompt {
+            tab: Some(id),
+            message: format!("{} has unsaved changes. ", text_tab.title),
+            choices,
+        }
+    }
+
+    fn delete(path: &Path) -> Prompt {
+        Prompt {
+            tab: None,
+            message: format!("Delete {}? ", path.display()),
+            choices: vec![
+                (
+                    "delete",
+                    Msg::Delete {
+                        path: path.to_path_buf(),
+                    },
+                ),
+                ("cancel", Msg::DismissPrompt),
+            ],
+        }
+    }
+
+    fn replace(id: usize) -> Prompt {
+        let choice = |label, step| (label, Msg::Replace(step));
+        Prompt {
+            tab: Some(id),
+            message: "Replace this match? ".to_string(),
+            choices: vec![
+                choice("replace", ReplaceStep::Replace),
+                choice("skip", ReplaceStep::Skip),
+                choice("all", ReplaceStep::All),
+                choice("done", ReplaceStep::Done),
+            ],
+        }
+    }
+
+    fn running(id: usize, title: &str, job: &str) -> Prompt {
+        Prompt {
+            tab: Some(id),
+            message: format!("{title} is still running {job}. "),
+            choices: vec![
+                (
+                    "close",
+                    Msg::Tab {
+                        id,
+                        msg: TabMsg::Quit,
+                    },
+                ),
+                ("cancel", Msg::DismissPrompt),
+            ],
+        }
+    }
 }
 
 struct Editor {
@@ -290,6 +719,9 @@ struct Editor {
     config: Config,
 
     error_string: String,
+    prompt: Option<Prompt>,
+    /// The session as we last saved it, so we only write it when it changes.
+    saved_session: Vec<u8>,
 
     atlas: Rc<Atlas>,
 
@@ -298,6 +730,12 @@ struct Editor {
 
     meta: Meta,
     search_window: TextWindow,
+    search_options: SearchOptions,
+    replace_window: TextWindow,
+    replacing: Option<Replacing>,
+    grep_window: TextWindow,
+    grep: GrepResults,
+    goto_window: TextWindow,
 
     // template stuff
     template_path: PathBuf,
@@ -326,6 +764,33 @@ impl Editor {
         Ok(())
     }
 
+    fn load_language_model(&mut self) -> io::Result<()> {
+        if !self.config.language_model {
+            return Ok(());
+        }
+        let mut model = LanguageModel::new(
+            self.config.language_model_order,
+            self.config.language_model_weight,
+        );
+        if self.config.language_model_corpus.is_empty() {
+            model.train(HELP_TEXT);
+        }
+        // Keep whatever we managed to read, even if some of the paths are missing.
+        let mut result = Ok(());
+        for path in &self.config.language_model_corpus {
+            if let Err(e) = model.train_path(path) {
+                result = Err(io::Error::new(
+                    e.kind(),
+                    format!("language model corpus {}: {e}", path.display()),
+                ));
+            }
+        }
+        if !model.is_empty() {
+            self.text_stuff.language_model = Some(model);
+        }
+        result
+    }
+
     fn save_templates(&self) -> io::Result<()> {
         let file_contents = TemplateFile::new(&self.text_stuff, self.metrics.height);
         // NB: because the bulk of the data is long string content,
@@ -344,6 +809,212 @@ impl Editor {
         self.left_margin()
     }
 
+    fn session(&self) -> Session {
+        let mut session = Session::default();
+        for (id, tab) in &self.tabs {
+            let session_tab = match tab {
+                TabType::Text(TextTab {
+                    path: Some(path),
+                    text,
+                    ..
+                }) => SessionTab::Text {
+                    path: path.clone(),
+                    origin: text.origin,
+                    selection: text.selection_coords(),
+                    wrap: text.wrap,
+                },
+                TabType::Shell(shell_tab) => match shell_tab.working_dir() {
+                    Ok(working_dir) => SessionTab::Shell { working_dir },
+                    Err(_) => continue,
+                },
+                // Unnamed buffers can only be recovered from their swap files.
+                _ => continue,
+            };
+            if matches!(self.tab, Tab::Edit(active) if active == *id) {
+                session.active = Some(session.tabs.len());
+            }
+            session.tabs.push(session_tab);
+        }
+        session
+    }
+
+    fn save_session(&mut self) {
+        if !self.config.restore_session {
+            return;
+        }
+        let result = self.session().to_json().and_then(|json| {
+            if json != self.saved_session {
+                Session::save(&json)?;
+                self.saved_session = json;
+            }
+            Ok(())
+        });
+        self.report_error(result);
+    }
+
+    /// Open a file in a new tab, and switch to it.
+    fn open(&mut self, path: PathBuf) {
+        // If we reopen meta, we're likely to want another file in the same dir.
+        if let Some(dir) = path.parent().and_then(full_path) {
+            self.meta.path_window.buffer = TextBuffer::from_string(&dir);
+            self.meta.reload_suggestions();
+        }
+
+        if let Some(file_contents) = self.report_error(fs::read(&path)) {
+            let (format, buffer) = FileFormat::decode(&file_contents);
+            let text_tab = self.new_text_tab(Some(path.clone()), buffer, format);
+            text_tab.stamp = FileStamp::new(&path, &file_contents).ok();
+            let restored = history::restore(&path, &file_contents, &mut text_tab.text);
+            self.report_error(restored);
+            self.meta.places.opened(&path);
+            let saved = self.meta.places.save();
+            self.report_error(saved);
+        }
+    }
+
+    fn open_shell(&mut self, working_dir: PathBuf) {
+        let id = self.take_id();
+        let shell = ShellTab::new(
+            id,
+            self.atlas.clone(),
+            self.metrics.clone(),
+            self.max_dimensions(),
+            self.sender.clone(),
+            working_dir,
+        )
+        .unwrap();
+        self.tabs.insert(id, TabType::Shell(shell));
+        self.tab = Tab::Edit(id);
+    }
+
+    /// Reopen the tabs from a previous session, returning whether any were restored.
+    /// The session is saved once at the end, so it's never left half-restored.
+    fn restore_session(&mut self, session: Session) -> bool {
+        let mut active = None;
+        for (i, session_tab) in session.tabs.into_iter().enumerate() {
+            let tab_count = self.tabs.len();
+            match session_tab {
+                SessionTab::Text {
+                    path,
+                    origin,
+                    selection,
+                    wrap,
+                } => {
+                    self.open(path);
+                    if self.tabs.len() == tab_count {
+                        continue;
+                    }
+                    if let Tab::Edit(id) = self.tab {
+                        if let Some(TabType::Text(text_tab)) = self.tabs.get_mut(&id) {
+                            let text = &mut text_tab.text;
+                            text.wrap = wrap;
+                            // The file may have shrunk since; if so, the old position and
+                            // selection don't mean much anymore.
+                            let last_row = text.buffer.line_count().saturating_sub(1);
+                            if origin.0 <= last_row {
+                                text.origin = origin;
+                                text.set_selection(selection);
+                            } else {
+                                text.origin = (last_row, 0);
+                            }
+                        }
+                    }
+                }
+                SessionTab::Shell { working_dir } => {
+                    if !working_dir.is_dir() {
+                        continue;
+                    }
+                    self.open_shell(working_dir);
+                }
+            }
+            if session.active == Some(i) {
+                active = Some(self.tab.clone());
+            }
+        }
+        self.tab = active.unwrap_or(Tab::Meta);
+        self.save_session();
+        !self.tabs.is_empty()
+    }
+
+    fn close_tab(&mut self, id: usize) {
+        if self.prompt.as_ref().is_some_and(|p| p.tab == Some(id)) {
+            self.prompt = None;
+        }
+        match self.tabs.remove(&id) {
+            Some(TabType::Text(mut text_tab)) => {
+                text_tab.text.settle_all(&mut self.text_stuff);
+                if text_tab.swapped.is_some() {
+                    self.report_error(swap::remove(id));
+                }
+            }
+            Some(TabType::Shell(mut shell_tab)) => {
+                shell_tab.shell_output.settle_all(&mut self.text_stuff);
+            }
+            None => {}
+        }
+    }
+
+    /// Ask the user what to do if a file has been changed on disk behind our back.
+    fn check_disk(&mut self, id: usize) {
+        if let Some(TabType::Text(text_tab)) = self.tabs.get(&id) {
+            match text_tab.changed_on_disk() {
+                Ok(true) => {
+                    self.prompt = Some(Prompt::changed_on_disk(id, &text_tab.title, false, false))
+                }
+                Ok(false) => {}
+                Err(e) => self.error_string = format!("Error: {e}"),
+            }
+        }
+    }
+
+    /// Render a section of the meta tab listing some places, each with when it was last opened.
+    fn render_places(&self, view: &mut View<Msg>, title: &str, places: &[Place], pinned: bool) {
+        if places.is_empty() {
+            return;
+        }
+        let entry_height = DEFAULT_CHAR_HEIGHT * 3 / 2;
+        Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, title).render_split(view, Side::Top, 0.0);
+
+        let now = now();
+        for place in places {
+            let mut place_view = view.split_off(Side::Top, entry_height);
+            place_view.split_off(Side::Left, 20);
+
+            let msg = match full_path(&place.path) {
+                Some(current_path) if current_path.ends_with('/') => Msg::MetaPath { current_path },
+                _ => Msg::Open {
+                    path: place.path.clone(),
+                },
+            };
+            let label = place.path.to_string_lossy();
+            Button::new(&label, msg.clone(), true).render_split(&mut place_view, Side::Left, 0.5);
+
+            let mut buttons = vec![Button::new(&format_age(place.opened, now), msg, false)];
+            if pinned {
+                buttons.push(Button::new(
+                    "unpin",
+                    Msg::TogglePin {
+                        path: place.path.clone(),
+                    },
+                    true,
+                ));
+            }
+            Spaced(40, &buttons).render_split(&mut place_view, Side::Right, 0.5);
+        }
+
+        view.split_off(Side::Top, entry_height);
+    }
+
+    fn autosave(&mut self) {
+        let mut result = Ok(());
+        for (id, tab) in &mut self.tabs {
+            if let TabType::Text(text_tab) = tab {
+                result = result.and(text_tab.autosave(*id));
+            }
+        }
+        self.report_error(result);
+    }
+
     pub fn report_error<A, E: Display>(&mut self, result: Result<A, E>) -> Option<A> {
         match result {
             Ok(a) => Some(a),
@@ -398,19 +1069,34 @@ impl Widget for Editor {
                                             text_tab.text.buffer.content_string(),
                                         ),
                                         results: vec![],
+                                        offset: 0,
                                     },
                                 },
                                 true,
                             ))
                         }
                         buttons.extend([
+                            Button::new(
+                                "go to",
+                                Msg::SwitchTab {
+                                    tab: Tab::Goto { id },
+                                },
+                                true,
+                            ),
+                            Button::new(
+                                "history",
+                                Msg::SwitchTab {
+                                    tab: Tab::History { id, offset: 0 },
+                                },
+                                true,
+                            ),
                             Button::new(
                                 "undo",
                                 Msg::Tab {
                                     id,
                                     msg: TabMsg::Undo,
                                 },
-                                !text_tab.text.undos.is_empty(),
+                                text_tab.text.undo_tree.can_undo(),
                             ),
                             Button::new(
                                 "redo",
@@ -418,7 +1104,7 @@ impl Widget for Editor {
                                     id,
                                     msg: TabMsg::Redo,
                                 },
-                                !text_tab.text.redos.is_empty(),
+                                text_tab.text.undo_tree.can_redo(),
                             ),
                             Button::new(
                                 "save",
@@ -456,9 +1142,77 @@ impl Widget for Editor {
                 head_text.render_split(&mut header, Side::Left, 0.5);
                 header.leave_rest_blank();
             }
-            Tab::Search { id, .. } => {
+            Tab::Search {
+                id, ref results, ..
+            } => {
+                let head_text = Button::new("find", Msg::SwitchTab { tab: Tab::Edit(id) }, true);
+                head_text.render_split(&mut header, Side::Left, 0.5);
+                let options = self.search_options;
+                let has_results = !results.is_empty();
+                Spaced(
+                    40,
+                    &[
+                        Button::new(
+                            if options.regex { "regex" } else { "literal" },
+                            Msg::SearchOptions(SearchOptions {
+                                regex: !options.regex,
+                                ..options
+                            }),
+                            true,
+                        ),
+                        Button::new(
+                            if options.ignore_case {
+                                "ignore case"
+                            } else {
+                                "match case"
+                            },
+                            Msg::SearchOptions(SearchOptions {
+                                ignore_case: !options.ignore_case,
+                                ..options
+                            }),
+                            true,
+                        ),
+                        Button::new("replace", Msg::StartReplace, has_results),
+                    ],
+                )
+                .render_placed(header, 1.0, 0.5);
+            }
+            Tab::History { id, .. } => {
+                let head_text = Button::new("history", Msg::SwitchTab { tab: Tab::Edit(id) }, true);
+                head_text.render_split(&mut header, Side::Left, 0.5);
                 header.leave_rest_blank();
             }
+            Tab::Goto { id } => {
+                let head_text = Button::new("go to", Msg::SwitchTab { tab: Tab::Edit(id) }, true);
+                head_text.render_split(&mut header, Side::Left, 0.5);
+                let scroll_to = |row| Msg::Tab {
+                    id,
+                    msg: TabMsg::ScrollTo { row },
+                };
+                Spaced(
+                    40,
+                    &[
+                        Button::new("start", scroll_to(0), true),
+                        Button::new("end", scroll_to(usize::MAX), true),
+                        Button::new(
+                            "go",
+                            Msg::Tab {
+                                id,
+                                msg: TabMsg::GotoLine,
+                            },
+                            true,
+                        ),
+                    ],
+                )
+                .render_placed(header, 1.0, 0.5);
+            }
+            Tab::Grep { .. } => {
+                let title = format!("grep {}", self.grep.root.display());
+                let head_text = Button::new(&title, Msg::SwitchTab { tab: Tab::Meta }, true);
+                head_text.render_split(&mut header, Side::Left, 0.5);
+                Spaced(40, &[Button::new("search", Msg::RunGrep, true)])
+                    .render_placed(header, 1.0, 0.5);
+            }
         }
 
         {
@@ -466,19 +1220,140 @@ impl Widget for Editor {
             footer.split_off(Side::Left, self.left_margin());
             footer.split_off(Side::Right, self.right_margin());
 
+            if let Some(prompt) = &self.prompt {
+                let buttons: Vec<_> = prompt
+                    .choices
+                    .iter()
+                    .map(|(label, msg)| Button::new(label, msg.clone(), true))
+                    .collect();
+                Spaced(40, &buttons).render_split(&mut footer, Side::Right, 0.4);
+            } else if let Tab::Edit(id) = self.tab {
+                if let TabType::Text(text_tab) = &self.tabs[&id] {
+                    let format = &text_tab.format;
+                    Spaced(
+                        40,
+                        &[
+                            Button::new(
+                                format.encoding.name(),
+                                Msg::Tab {
+                                    id,
+                                    msg: TabMsg::CycleEncoding,
+                                },
+                                true,
+                            ),
+                            Button::new(
+                                format.line_ending.name(),
+                                Msg::Tab {
+                                    id,
+                                    msg: TabMsg::ToggleLineEnding,
+                                },
+                                true,
+                            ),
+                            Button::new(
+                                if format.bom { "BOM" } else { "no BOM" },
+                                Msg::Tab {
+                                    id,
+                                    msg: TabMsg::ToggleBom,
+                                },
+                                !format.encoding.bom().is_empty(),
+                            ),
+                            Button::new(
+                                if text_tab.text.wrap {
+                                    "wrap"
+                                } else {
+                                    "no wrap"
+                                },
+                                Msg::Tab {
+                                    id,
+                                    msg: TabMsg::ToggleWrap,
+                                },
+                                true,
+                            ),
+                        ],
+                    )
+                    .render_split(&mut footer, Side::Right, 0.4);
+
+                    // The correction strip: each of the last few recognized chars, followed by
+                    // the other chars it might have been.
+                    let alternatives = text_tab.text.alternatives();
+                    let skip = alternatives.len().saturating_sub(CORRECTION_CELLS);
+                    let cells: Vec<Vec<_>> = alternatives
+                        .into_iter()
+                        .skip(skip)
+                        .filter(|(_, _, others)| !others.is_empty())
+                        .map(|(coord, current, others)| {
+                            let correct = |char| Msg::Tab {
+                                id,
+                                msg: TabMsg::Correct { coord, char },
+                            };
+                            std::iter::once(Button::new(
+                                &format!("{current}:"),
+                                correct(current),
+                                false,
+                            ))
+                            .chain(
+                                others
+                                    .into_iter()
+                                    .map(|c| Button::new(&c.to_string(), correct(c), true)),
+                            )
+                            .collect()
+                        })
+                        .collect();
+                    if !cells.is_empty() {
+                        let strip: Vec<_> = cells.iter().map(|cell| Spaced(20, cell)).collect();
+                        Spaced(40, &strip).render_split(&mut footer, Side::Left, 0.4);
+                        footer.split_off(Side::Left, 40);
+                    }
+                }
+            }
+
             let mut message = match self.tab {
                 Tab::Meta => "".to_string(),
                 Tab::Template => "".to_string(),
-                Tab::Edit(id) => {
-                    let (row, col) = match &self.tabs[&id] {
-                        TabType::Text(text_tab) => text_tab.text.origin,
-                        TabType::Shell(shell_tab) => shell_tab.shell_output.origin,
-                    };
-                    format!("[{row}:{col}] ")
+                Tab::Edit(id) => match &self.tabs[&id] {
+                    TabType::Text(text_tab) => {
+                        let (row, col) = text_tab.text.origin;
+                        if has_final_newline(&text_tab.text.buffer) {
+                            format!("[{row}:{col}] ")
+                        } else {
+                            format!("[{row}:{col}] no final newline ")
+                        }
+                    }
+                    TabType::Shell(shell_tab) => {
+                        let (row, col) = shell_tab.shell_output.origin;
+                        format!("[{row}:{col}] ")
+                    }
+                },
+                Tab::Search {
+                    ref results,
+                    offset,
+                    ..
+                } if !results.is_empty() => {
+                    format!(
+                        "{}-{} of {} matches ",
+                        offset + 1,
+                        (offset + self.max_dimensions().0).min(results.len()),
+                        results.len()
+                    )
                 }
                 Tab::Search { .. } => "".to_string(),
+                Tab::History { .. } => "".to_string(),
+                Tab::Goto { .. } => "".to_string(),
+                Tab::Grep { .. } if self.grep.query.is_empty() => "".to_string(),
+                Tab::Grep { .. } => {
+                    let matches = self.grep.match_count();
+                    let files = self.grep.files.len();
+                    if self.grep.truncated {
+                        format!("first {matches} matches in {files} files ")
+                    } else {
+                        format!("{matches} matches in {files} files ")
+                    }
+                }
             };
 
+            if let Some(prompt) = &self.prompt {
+                message.push_str(&prompt.message);
+            }
             message.push_str(&self.error_string);
 
             let text = Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, &message);
@@ -523,20 +1398,83 @@ impl Widget for Editor {
                     40,
                     &[
                         Button::new("new file", Msg::New, !written_path.exists()),
+                        Button::new("new dir", Msg::MakeDir, !written_path.exists()),
                         Button::new(
                             "new shell",
                             Msg::OpenShell {
-                                working_dir: written_dir,
+                                working_dir: written_dir.clone(),
                             },
                             true,
                         ),
+                        Button::new("grep", Msg::StartGrep { root: written_dir }, true),
+                        Button::new(
+                            if self.meta.places.is_pinned(&written_path) {
+                                "unpin"
+                            } else {
+                                "pin"
+                            },
+                            Msg::TogglePin {
+                                path: written_path.clone(),
+                            },
+                            written_path.exists(),
+                        ),
                     ],
                 )
                 .render_split(&mut buttons, Side::Right, 0.5);
 
                 buttons.leave_rest_blank();
+
+                if let Some((op, from)) = &self.meta.pending {
+                    let mut pending_view = view.split_off(Side::Top, entry_height);
+                    let label = format!("{} {} to the path above", op.name(), from.display());
+                    Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, &label).render_split(
+                        &mut pending_view,
+                        Side::Left,
+                        0.5,
+                    );
+                    Spaced(
+                        40,
+                        &[
+                            Button::new(op.name(), Msg::ApplyFileOp, !written_path.exists()),
+                            Button::new("cancel", Msg::CancelFileOp, true),
+                        ],
+                    )
+                    .render_split(&mut pending_view, Side::Right, 0.5);
+                    pending_view.leave_rest_blank();
+                }
+
                 view.split_off(Side::Top, entry_height);
 
+                if !self.meta.recoverable.is_empty() {
+                    Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, "Recover:").render_split(
+                        &mut view,
+                        Side::Top,
+                        0.0,
+                    );
+
+                    let now = now();
+                    for (file, swap) in &self.meta.recoverable {
+                        let mut swap_view = view.split_off(Side::Top, entry_height);
+                        swap_view.split_off(Side::Left, 20);
+
+                        let label = format!("{} ({})", swap.title, format_age(swap.time, now));
+                        Button::new(&label, Msg::Recover { file: file.clone() }, true)
+                            .render_split(&mut swap_view, Side::Left, 0.5);
+
+                        Spaced(
+                            40,
+                            &[Button::new(
+                                "discard",
+                                Msg::DiscardSwap { file: file.clone() },
+                                true,
+                            )],
+                        )
+                        .render_split(&mut swap_view, Side::Right, 0.5);
+                    }
+
+                    view.split_off(Side::Top, entry_height);
+                }
+
                 Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, "Tabs:").render_split(
                     &mut view,
                     Side::Top,
@@ -574,7 +1512,7 @@ impl Widget for Editor {
                                     "close",
                                     Msg::Tab {
                                         id: *tab_id,
-                                        msg: TabMsg::Quit,
+                                        msg: TabMsg::Close,
                                     },
                                     true,
                                 ),
@@ -587,7 +1525,7 @@ impl Widget for Editor {
                                 "close",
                                 Msg::Tab {
                                     id: *tab_id,
-                                    msg: TabMsg::Quit,
+                                    msg: TabMsg::Close,
                                 },
                                 true,
                             )],
@@ -598,30 +1536,94 @@ impl Widget for Editor {
 
                 view.split_off(Side::Top, entry_height);
 
+                self.render_places(&mut view, "Pinned:", &self.meta.places.pinned, true);
+                self.render_places(&mut view, "Recent:", &self.meta.places.recent, false);
+
+                if !self.meta.found.is_empty() {
+                    Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, "Found:").render_split(
+                        &mut view,
+                        Side::Top,
+                        0.0,
+                    );
+
+                    for path in &self.meta.found {
+                        let mut found_view = view.split_off(Side::Top, entry_height);
+                        found_view.split_off(Side::Left, 20);
+                        Button::new(
+                            &path.to_string_lossy(),
+                            Msg::Open { path: path.clone() },
+                            true,
+                        )
+                        .render_split(&mut found_view, Side::Left, 0.5);
+                    }
+
+                    view.split_off(Side::Top, entry_height);
+                }
+
+                let mut paths_header = view.split_off(Side::Top, entry_height);
                 Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, "Paths:").render_split(
-                    &mut view,
-                    Side::Top,
-                    0.0,
+                    &mut paths_header,
+                    Side::Left,
+                    0.5,
                 );
+                Spaced(
+                    40,
+                    &[Button::new(
+                        if self.meta.show_hidden {
+                            "hide hidden"
+                        } else {
+                            "show hidden"
+                        },
+                        Msg::ToggleHidden,
+                        true,
+                    )],
+                )
+                .render_split(&mut paths_header, Side::Right, 0.5);
 
-                for s in &self.meta.suggested {
+                let now = now();
+                for entry in &self.meta.suggested {
                     if view.size().y < entry_height {
                         break;
                     }
                     let mut suggest_view = view.split_off(Side::Top, entry_height);
                     suggest_view.split_off(Side::Left, 20);
 
-                    let msg = if s.ends_with('/') {
+                    let path = PathBuf::from(&entry.path);
+                    let is_dir = entry.path.ends_with('/');
+                    let msg = if is_dir {
                         Msg::MetaPath {
-                            current_path: s.clone(),
+                            current_path: entry.path.clone(),
                         }
                     } else {
-                        Msg::Open {
-                            path: PathBuf::from(s),
-                        }
+                        Msg::Open { path: path.clone() }
                     };
 
-                    Button::new(s, msg, true).render_split(&mut suggest_view, Side::Left, 0.5);
+                    Button::new(&entry.name, msg.clone(), true).render_split(
+                        &mut suggest_view,
+                        Side::Left,
+                        0.5,
+                    );
+
+                    let age = format_age(entry.modified, now);
+                    let info = if is_dir {
+                        age
+                    } else {
+                        format!("{} {age}", format_size(entry.size))
+                    };
+                    let mark = |op| Msg::MarkPath {
+                        path: path.clone(),
+                        op,
+                    };
+                    Spaced(
+                        40,
+                        &[
+                            Button::new(&info, msg, false),
+                            Button::new("rename", mark(FileOp::Rename), true),
+                            Button::new("duplicate", mark(FileOp::Duplicate), true),
+                            Button::new("delete", Msg::ConfirmDelete { path: path.clone() }, true),
+                        ],
+                    )
+                    .render_split(&mut suggest_view, Side::Right, 0.5);
                 }
             }
             Tab::Edit(id) => {
@@ -633,9 +1635,13 @@ impl Widget for Editor {
                         // Based on the top margin of the text area and the baseline height.
                         // TODO: calculate this from other metrics.
                         margin_view.split_off(Side::Top, 7);
-                        for row in (text_tab.text.origin.0..).take(text_tab.text.dimensions.0) {
+                        for (row, col) in text_tab.text.screen_rows() {
                             let view =
                                 margin_view.split_off(Side::Top, text_tab.text.grid_metrics.height);
+                            if text_tab.text.wrap && col > 0 {
+                                // Only number the first row of a wrapped line.
+                                continue;
+                            }
                             let text = Text::literal(
                                 text_tab.text.grid_metrics.height * 3 / 4,
                                 &*FONT,
@@ -645,6 +1651,32 @@ impl Widget for Editor {
                         }
                         margin_view.leave_rest_blank();
 
+                        let mut scroll_view =
+                            view.split_off(Side::Right, self.right_margin() - GRID_BORDER * 2);
+                        scroll_view.split_off(Side::Top, 7);
+                        let text = &text_tab.text;
+                        let (rows, _) = text.dimensions;
+                        let lines = text.buffer.line_count().max(1);
+                        let first_visible = text.origin.0;
+                        let last_visible = text
+                            .screen_rows()
+                            .last()
+                            .map_or(first_visible, |(row, _)| *row);
+                        for segment in 0..rows {
+                            // The range of rows this bit of the scrollbar stands for.
+                            let start = segment * lines / rows;
+                            let end = ((segment + 1) * lines / rows).max(start + 1);
+                            let onscreen = start <= last_visible && end > first_visible;
+                            let mut segment_view =
+                                scroll_view.split_off(Side::Top, text.grid_metrics.height);
+                            segment_view.handlers().on_tap(Msg::Tab {
+                                id: *id,
+                                msg: TabMsg::ScrollTo { row: start },
+                            });
+                            segment_view.draw(&ScrollSegment { onscreen });
+                        }
+                        scroll_view.leave_rest_blank();
+
                         text_tab
                             .text
                             .borrow()
@@ -714,8 +1746,8 @@ impl Widget for Editor {
                 id,
                 contents,
                 results,
+                offset,
             } => {
-                // TODO: line numbers!
                 view.split_off(Side::Left, self.left_margin());
 
                 self.search_window
@@ -726,17 +1758,157 @@ impl Widget for Editor {
                     })
                     .render_split(&mut view, Side::Top, 0.0);
 
-                for result in results {
-                    let line = contents.line(*result);
-                    let button = Button::new(line, Msg::SearchResult(*id, *result), true);
-                    button.render_split(&mut view, Side::Top, 0.0);
+                Text::literal(DEFAULT_CHAR_HEIGHT, &*FONT, "Replace with:").render_split(
+                    &mut view,
+                    Side::Top,
+                    0.0,
+                );
+                self.replace_window
+                    .borrow()
+                    .map(|message| match message {
+                        TextMessage::Write(ink) => Msg::WriteReplacement { ink },
+                        TextMessage::Erase(ink) => Msg::EraseReplacement { ink },
+                    })
+                    .render_split(&mut view, Side::Top, 0.0);
+
+                let entry_height = DEFAULT_CHAR_HEIGHT + 20;
+                let (rows, _) = self.max_dimensions();
+                for result in results.iter().skip(*offset).take(rows) {
+                    if view.size().y < entry_height {
+                        break;
+                    }
+                    let start = contents.coord(result.start);
+                    let end = contents.coord(result.end);
+                    let line = contents.line(start.0).trim_end_matches('\n');
+                    // Show some of the line before the match, but not so much it's pushed offscreen.
+                    let skip = start.1.saturating_sub(SEARCH_CONTEXT_CHARS);
+                    let ellipsis = if skip > 0 { "…" } else { "" };
+                    let text: String = line.chars().skip(skip).collect();
+                    let label = format!("{:>4}: {ellipsis}{text}", start.0);
+                    let mut entry_view = view.split_off(Side::Top, entry_height);
+                    Button::new(&label, Msg::SearchResult(*id, start, end), true).render_split(
+                        &mut entry_view,
+                        Side::Left,
+                        0.5,
+                    );
+                }
+            }
+            Tab::History { id, offset } => {
+                view.split_off(Side::Left, self.left_margin());
+                let entry_height = DEFAULT_CHAR_HEIGHT + 20;
+                if let Some(TabType::Text(text_tab)) = self.tabs.get(id) {
+                    let now = now();
+                    let (rows, _) = self.max_dimensions();
+                    for entry in text_tab.text.undo_tree.entries().skip(*offset).take(rows) {
+                        let marker = if entry.current { ">" } else { " " };
+                        let label =
+                            format!("{marker} {} {}", format_age(entry.time, now), entry.summary);
+                        let mut entry_view = view.split_off(Side::Top, entry_height);
+                        entry_view.split_off(Side::Left, 20);
+                        Button::new(
+                            &label,
+                            Msg::Tab {
+                                id: *id,
+                                msg: TabMsg::JumpHistory(entry.id),
+                            },
+                            !entry.current,
+                        )
+                        .render_split(&mut entry_view, Side::Left, 0.5);
+                    }
+                }
+            }
+            Tab::Goto { .. } => {
+                view.split_off(Side::Left, self.left_margin());
+                Text::literal(
+                    DEFAULT_CHAR_HEIGHT,
+                    &*FONT,
+                    "Line number, from 0 as in the margin:",
+                )
+                .render_split(&mut view, Side::Top, 0.0);
+                self.goto_window
+                    .borrow()
+                    .map(|message| match message {
+                        TextMessage::Write(ink) => Msg::Write { ink },
+                        TextMessage::Erase(ink) => Msg::Erase { ink },
+                    })
+                    .render_split(&mut view, Side::Top, 0.0);
+            }
+            Tab::Grep { offset } => {
+                view.split_off(Side::Left, self.left_margin());
+
+                self.grep_window
+                    .borrow()
+                    .map(|message| match message {
+                        TextMessage::Write(ink) => Msg::Write { ink },
+                        TextMessage::Erase(ink) => Msg::Erase { ink },
+                    })
+                    .render_split(&mut view, Side::Top, 0.0);
+
+                let entry_height = DEFAULT_CHAR_HEIGHT + 20;
+                view.split_off(Side::Top, entry_height / 2);
+                for row in self.grep.rows().skip(*offset) {
+                    if view.size().y < entry_height {
+                        break;
+                    }
+                    let mut row_view = view.split_off(Side::Top, entry_height);
+                    match row {
+                        GrepRow::File(file) => {
+                            let path = file
+                                .path
+                                .strip_prefix(&self.grep.root)
+                                .unwrap_or(&file.path);
+                            let label = format!("{} ({})", path.display(), file.match_count());
+                            Button::new(
+                                &label,
+                                Msg::Open {
+                                    path: file.path.clone(),
+                                },
+                                true,
+                            )
+                            .render_split(
+                                &mut row_view,
+                                Side::Left,
+                                0.5,
+                            );
+                        }
+                        GrepRow::Line(path, line) => {
+                            row_view.split_off(Side::Left, 40);
+                            let label = format!("{:>4}: {}", line.number, line.text);
+                            Button::new(
+                                &label,
+                                Msg::OpenAt {
+                                    path: path.to_path_buf(),
+                                    line: line.number,
+                                },
+                                line.is_match,
+                            )
+                            .render_split(
+                                &mut row_view,
+                                Side::Left,
+                                0.5,
+                            );
+                        }
+                    }
                 }
             }
         }
     }
 }
 
+/// Get the command name and parent pid out of the contents of `/proc/<pid>/stat`.
+fn parse_stat(stat: &str) -> Option<(&str, u32)> {
+    // The name is in parens, and may itself contain spaces or parens.
+    let (start, rest) = stat.split_once('(')?;
+    let (name, fields) = rest.rsplit_once(')')?;
+    start.trim().parse::<u32>().ok()?;
+    let ppid = fields.split_whitespace().nth(1)?.parse().ok()?;
+    Some((name, ppid))
+}
+
 const NUM_SUGGESTIONS: usize = 32;
+const NUM_FOUND: usize = 8;
+const SEARCH_CONTEXT_CHARS: usize = 20;
+const MAX_DIFF_EDITS: usize = 2000;
 const MAX_DIR_ENTRIES: usize = 1024;
 
 fn full_path(path: &Path) -> Option<String> {
@@ -747,7 +1919,17 @@ fn full_path(path: &Path) -> Option<String> {
     Some(string)
 }
 
-fn suggestions(current_path: &str) -> io::Result<Vec<String>> {
+/// A file or directory in the Paths list.
+pub struct PathEntry {
+    /// The full path, with a trailing `/` for directories.
+    path: String,
+    name: String,
+    size: u64,
+    /// In seconds since the epoch.
+    modified: u64,
+}
+
+fn suggestions(current_path: &str, show_hidden: bool) -> io::Result<Vec<PathEntry>> {
     if !current_path.starts_with('/') {
         // All paths must be absolute.
         return Ok(vec![]);
@@ -758,22 +1940,55 @@ fn suggestions(current_path: &str) -> io::Result<Vec<String>> {
     let mut results: Vec<_> = read
         .filter_map(|r| r.ok())
         .filter(|de| {
-            de.file_name()
-                .to_str()
-                .into_iter()
-                .any(|s| s.starts_with(file))
+            de.file_name().to_str().into_iter().any(|s| {
+                // Hidden files are only listed on request, or if we're already writing one.
+                s.starts_with(file) && (show_hidden || file.starts_with('.') || !s.starts_with('.'))
+            })
         })
         .take(MAX_DIR_ENTRIES)
-        .filter_map(|s| full_path(&s.path()))
+        .filter_map(|de| {
+            let path = full_path(&de.path())?;
+            let metadata = fs::metadata(de.path()).ok();
+            let modified = metadata
+                .as_ref()
+                .and_then(|m| m.modified().ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |d| d.as_secs());
+            let mut name = de.file_name().to_string_lossy().into_owned();
+            if path.ends_with('/') {
+                name.push('/');
+            }
+            Some(PathEntry {
+                path,
+                name,
+                size: metadata.map_or(0, |m| m.len()),
+                modified,
+            })
+        })
         .collect();
 
     // NB: if this is slow, pull in the partial sort crate.
-    results.sort();
+    results.sort_by(|a, b| a.path.cmp(&b.path));
     results.truncate(NUM_SUGGESTIONS);
 
     Ok(results)
 }
 
+fn file_title(path: Option<&Path>) -> String {
+    path.and_then(|p| p.file_name())
+        .map(|p| p.to_string_lossy().into_owned())
+        .unwrap_or("<unnamed file>".to_string())
+}
+
+/// Page through a list of entries, keeping one entry from the previous page for context.
+fn page_offset(offset: usize, rows: usize, entries: usize, towards: Side) -> usize {
+    match towards {
+        Side::Top if offset + rows < entries => offset + rows - 1,
+        Side::Bottom => offset - (rows - 1).min(offset),
+        _ => offset,
+    }
+}
+
 fn max_dimensions(metrics: &Metrics) -> Coord {
     let rows = (SCREEN_HEIGHT - TOP_MARGIN * 2 - GRID_BORDER * 2) / metrics.height;
     let cols = (SCREEN_WIDTH - LEFT_MARGIN * 2 - GRID_BORDER * 2) / metrics.width;
@@ -785,6 +2000,131 @@ impl Editor {
         max_dimensions(&self.metrics)
     }
 
+    /// Select the next match for the replace in progress, and ask what to do with it; or, if
+    /// there are no more matches, finish up.
+    fn next_replacement(&mut self) {
+        let replacing = match &mut self.replacing {
+            Some(replacing) => replacing,
+            None => return,
+        };
+        let id = replacing.id;
+        let found = match self.tabs.get_mut(&id) {
+            Some(TabType::Text(text_tab)) => {
+                let contents = IndexedString::index(text_tab.text.buffer.content_string());
+                let from = contents.index_of(replacing.from);
+                search::next_match(&replacing.matcher, contents.as_str(), from).map(|range| {
+                    let start = contents.coord(range.start);
+                    text_tab.text.select_span(start, contents.coord(range.end));
+                    replacing.from = start;
+                })
+            }
+            _ => None,
+        };
+        if found.is_some() {
+            self.prompt = Some(Prompt::replace(id));
+        } else {
+            self.error_string = format!("Replaced {} matches.", replacing.replaced);
+            self.replacing = None;
+            self.prompt = None;
+        }
+    }
+
+    fn replace_step(&mut self, step: ReplaceStep) {
+        let replacing = match &mut self.replacing {
+            Some(replacing) => replacing,
+            None => return,
+        };
+        let text_tab = match self.tabs.get_mut(&replacing.id) {
+            Some(TabType::Text(text_tab)) => text_tab,
+            _ => {
+                self.replacing = None;
+                self.prompt = None;
+                return;
+            }
+        };
+        // Find the current match again, in case the text has been edited since we last looked.
+        let contents = IndexedString::index(text_tab.text.buffer.content_string());
+        let from = contents.index_of(replacing.from);
+        let text = contents.as_str();
+        let range = match search::next_match(&replacing.matcher, text, from) {
+            Some(range) => range,
+            None => {
+                self.next_replacement();
+                return;
+            }
+        };
+        let start = contents.coord(range.start);
+        let end = contents.coord(range.end);
+        let (matcher, template, options) = (
+            &replacing.matcher,
+            &replacing.replacement,
+            replacing.options,
+        );
+        match step {
+            ReplaceStep::Replace => {
+                let replaced = search::replacement(matcher, text, range, template, options);
+                let content = TextBuffer::from_string(&replaced);
+                replacing.from = add_coord(start, content.end());
+                replacing.replaced += 1;
+                text_tab.text.replace(Replace {
+                    from: start,
+                    until: end,
+                    content,
+                });
+                text_tab.dirty = true;
+            }
+            ReplaceStep::Skip => {
+                replacing.from = end;
+            }
+            ReplaceStep::All => {
+                let replacements = search::replace_all(matcher, text, from, template, options);
+                replacing.replaced += replacements.len();
+                // Work backwards, so the earlier coordinates stay valid; and do it all as one
+                // edit, so it can be undone in one go.
+                text_tab.text.transaction(|window| {
+                    for (range, replaced) in replacements.into_iter().rev() {
+                        window.replace(Replace {
+                            from: contents.coord(range.start),
+                            until: contents.coord(range.end),
+                            content: TextBuffer::from_string(&replaced),
+                        });
+                    }
+                });
+                text_tab.text.set_selection(Selection::Normal);
+                text_tab.dirty = true;
+                replacing.from = contents.coord(text.len());
+            }
+            ReplaceStep::Done => {
+                replacing.from = contents.coord(text.len());
+            }
+        }
+        self.next_replacement();
+    }
+
+    /// Rerun the query in the search tab, if it's open.
+    fn search(&mut self) {
+        if let Tab::Search {
+            contents,
+            results,
+            offset,
+            ..
+        } = &mut self.tab
+        {
+            let query = self.search_window.buffer.content_string();
+            *offset = 0;
+            match search::find_all(contents.as_str(), &query, self.search_options) {
+                Ok(found) => {
+                    *results = found;
+                    self.error_string.clear();
+                }
+                Err(e) => {
+                    results.clear();
+                    self.error_string = format!("Error: {e}");
+                }
+            }
+        }
+    }
+
     fn take_id(&mut self) -> usize {
         let id = self.next_tab_id;
         self.next_tab_id += 1;
@@ -802,28 +2142,39 @@ impl Editor {
         &mut ct.templates[col]
     }
 
-    fn new_text_tab(&mut self, path: Option<PathBuf>, contents: TextBuffer) {
+    fn new_text_tab(
+        &mut self,
+        path: Option<PathBuf>,
+        contents: TextBuffer,
+        format: FileFormat,
+    ) -> &mut TextTab {
         let id = self.take_id();
-        let title = path
-            .as_ref()
-            .and_then(|p| p.file_name())
-            .map(|p| p.to_string_lossy().into_owned())
-            .unwrap_or("<unnamed file>".to_string());
+        let title = file_title(path.as_deref());
+        let mut text = TextWindow::new(
+            contents,
+            self.atlas.clone(),
+            self.metrics.clone(),
+            self.max_dimensions(),
+        );
+        text.undo_limit = self.config.undo_limit.max(1);
         self.tabs.insert(
             id,
             TabType::Text(TextTab {
                 title,
                 path,
-                text: TextWindow::new(
-                    contents,
-                    self.atlas.clone(),
-                    self.metrics.clone(),
-                    self.max_dimensions(),
-                ),
+                text,
+                format,
                 dirty: false,
+                swapped: None,
+                backed_up: false,
+                stamp: None,
             }),
         );
-        self.tab = Tab::Edit(id)
+        self.tab = Tab::Edit(id);
+        match self.tabs.get_mut(&id) {
+            Some(TabType::Text(text_tab)) => text_tab,
+            _ => unreachable!("just inserted a text tab"),
+        }
     }
 }
 
@@ -831,6 +2182,24 @@ impl Applet for Editor {
     type Upstream = ();
 
     fn update(&mut self, message: Self::Message) -> Option<Self::Upstream> {
+        let session_changed = matches!(
+            message,
+            Msg::SwitchTab { .. }
+                | Msg::Open { .. }
+                | Msg::OpenAt { .. }
+                | Msg::OpenShell { .. }
+                | Msg::Recover { .. }
+                | Msg::Autosave
+                | Msg::Tab {
+                    msg: TabMsg::SaveAs { .. }
+                        | TabMsg::ToggleWrap
+                        | TabMsg::Close
+                        | TabMsg::SaveAndClose
+                        | TabMsg::Quit,
+                    ..
+                }
+        );
+
         match message {
             Msg::Write { ink, .. } => match &mut self.tab {
                 Tab::Meta => {
@@ -840,9 +2209,7 @@ impl Applet for Editor {
                         self.meta
                             .path_window
                             .ink_row(ink_type, &mut self.text_stuff);
-                        self.meta.suggested =
-                            suggestions(&self.meta.path_window.buffer.content_string())
-                                .unwrap_or_default();
+                        self.meta.reload_suggestions();
                     }
                 }
                 Tab::Edit(id) => match self.tabs.get_mut(id).unwrap() {
@@ -893,23 +2260,27 @@ impl Applet for Editor {
                         }
                     }
                 }
-                Tab::Search {
-                    id,
-                    contents,
-                    results,
-                } => {
+                Tab::Search { .. } => {
                     if let Some(ink_type) =
                         InkType::classify(&self.metrics, ink, &self.search_window.selection())
                     {
                         self.search_window.ink_row(ink_type, &mut self.text_stuff);
-                        // TODO: search
-                        let query = self.search_window.buffer.content_string();
-                        *results = contents
-                            .as_str()
-                            .match_indices(query.as_str())
-                            .take(16)
-                            .map(|(i, _)| contents.line_number(i))
-                            .collect();
+                        self.search();
+                    }
+                }
+                Tab::History { .. } => {}
+                Tab::Goto { .. } => {
+                    if let Some(ink_type) =
+                        InkType::classify(&self.metrics, ink, &self.goto_window.selection())
+                    {
+                        self.goto_window.ink_row(ink_type, &mut self.text_stuff);
+                    }
+                }
+                Tab::Grep { .. } => {
+                    if let Some(ink_type) =
+                        InkType::classify(&self.metrics, ink, &self.grep_window.selection())
+                    {
+                        self.grep_window.ink_row(ink_type, &mut self.text_stuff);
                     }
                 }
             },
@@ -922,6 +2293,7 @@ impl Applet for Editor {
                 }
                 Tab::Edit(id) => match self.tabs.get_mut(&id) {
                     Some(TabType::Text(tab)) => {
+                        tab.dirty = true;
                         tab.text.erase(ink);
                     }
                     Some(TabType::Shell(tab)) => {
@@ -931,6 +2303,14 @@ impl Applet for Editor {
                 },
                 Tab::Search { .. } => {
                     self.search_window.erase(ink);
+                    self.search();
+                }
+                Tab::History { .. } => {}
+                Tab::Goto { .. } => {
+                    self.goto_window.erase(ink);
+                }
+                Tab::Grep { .. } => {
+                    self.grep_window.erase(ink);
                 }
             },
             Msg::SwitchTab { tab } => {
@@ -940,6 +2320,9 @@ impl Applet for Editor {
                 }
                 self.error_string.clear();
                 self.tab = tab;
+                if let Tab::Edit(id) = self.tab {
+                    self.check_disk(id);
+                }
             }
             Msg::Swipe { towards } => match self.tab {
                 // TODO: abstract over the pattern here.
@@ -959,6 +2342,29 @@ impl Applet for Editor {
                         }
                     }
                 }
+                Tab::History { id, offset } => {
+                    let (rows, _) = self.max_dimensions();
+                    let entries = match self.tabs.get(&id) {
+                        Some(TabType::Text(text_tab)) => text_tab.text.undo_tree.entries().count(),
+                        _ => 0,
+                    };
+                    let offset = page_offset(offset, rows, entries, towards);
+                    self.tab = Tab::History { id, offset };
+                }
+                Tab::Grep { offset } => {
+                    let (rows, _) = self.max_dimensions();
+                    let entries = self.grep.rows().count();
+                    let offset = page_offset(offset, rows, entries, towards);
+                    self.tab = Tab::Grep { offset };
+                }
+                Tab::Search {
+                    ref results,
+                    ref mut offset,
+                    ..
+                } => {
+                    let (rows, _) = max_dimensions(&self.metrics);
+                    *offset = page_offset(*offset, rows, results.len(), towards);
+                }
                 Tab::Template => {
                     let (rows, _) = self.max_dimensions();
                     match towards {
@@ -978,44 +2384,142 @@ impl Applet for Editor {
                 }
             },
             Msg::Open { path } => {
-                // If we reopen meta, we're likely to want another file in the same dir.
-                if let Some(dir) = path.parent().and_then(full_path) {
-                    self.meta.path_window.buffer = TextBuffer::from_string(&dir);
+                self.open(path);
+            }
+            Msg::New => {
+                self.new_text_tab(None, TextBuffer::empty(), FileFormat::default());
+                self.error_string.clear();
+            }
+            Msg::Autosave => {
+                self.autosave();
+            }
+            Msg::Recover { file } => {
+                if let Some(i) = self.meta.recoverable.iter().position(|(f, _)| *f == file) {
+                    let (_, swap) = self.meta.recoverable.remove(i);
+                    let text_tab = self.new_text_tab(swap.path, swap.content, swap.format);
+                    text_tab.text.undo_tree = swap.undo_tree;
+                    text_tab.text.undo_tree.prune(text_tab.text.undo_limit);
+                    text_tab.dirty = true;
+                    // Snapshot the recovered tab before discarding the old swap file, so the
+                    // changes are never only in memory.
+                    self.autosave();
+                    self.report_error(swap::discard(&file));
+                }
+            }
+            Msg::DiscardSwap { file } => {
+                self.meta.recoverable.retain(|(f, _)| *f != file);
+                self.report_error(swap::discard(&file));
+            }
+            Msg::TogglePin { path } => {
+                self.meta.places.toggle_pin(&path);
+                let saved = self.meta.places.save();
+                self.report_error(saved);
+            }
+            Msg::MarkPath { path, op } => {
+                if let Some(current_path) = path.to_str() {
+                    self.meta.path_window.buffer = TextBuffer::from_string(current_path);
                     self.meta.reload_suggestions();
                 }
-
-                if let Some(file_contents) = self.report_error(fs::read_to_string(&path)) {
-                    self.new_text_tab(Some(path), TextBuffer::from_string(&file_contents));
+                self.meta.pending = Some((op, path));
+            }
+            Msg::ApplyFileOp => {
+                if let Some((op, from)) = self.meta.pending.take() {
+                    let to = PathBuf::from(self.meta.path_window.buffer.content_string());
+                    if self.report_error(op.apply(&from, &to)).is_some() && op == FileOp::Rename {
+                        // Keep any open tabs pointing at the file, or at files under the
+                        // directory.
+                        for tab in self.tabs.values_mut() {
+                            if let TabType::Text(text_tab) = tab {
+                                let renamed = text_tab
+                                    .path
+                                    .as_deref()
+                                    .and_then(|path| path.strip_prefix(&from).ok())
+                                    .map(|rest| match rest.as_os_str().is_empty() {
+                                        // Joining an empty path would add a trailing slash.
+                                        true => to.clone(),
+                                        false => to.join(rest),
+                                    });
+                                if let Some(path) = renamed {
+                                    text_tab.title = file_title(Some(&path));
+                                    text_tab.path = Some(path);
+                                }
+                            }
+                        }
+                    }
+                    self.meta.forget_index();
+                    self.meta.reload_suggestions();
                 }
             }
-            Msg::New => {
-                self.new_text_tab(None, TextBuffer::empty());
-                self.error_string.clear();
+            Msg::CancelFileOp => {
+                self.meta.pending = None;
+            }
+            Msg::ConfirmDelete { path } => {
+                self.prompt = Some(Prompt::delete(&path));
+            }
+            Msg::Delete { path } => {
+                self.prompt = None;
+                self.report_error(file_ops::delete(&path));
+                self.meta.forget_index();
+                self.meta.reload_suggestions();
+            }
+            Msg::MakeDir => {
+                let mut path = self.meta.path_window.buffer.content_string();
+                if self.report_error(fs::create_dir(&path)).is_some() {
+                    path.push('/');
+                    self.meta.path_window.buffer = TextBuffer::from_string(&path);
+                    self.meta.forget_index();
+                    self.meta.reload_suggestions();
+                }
+            }
+            Msg::ToggleHidden => {
+                self.meta.show_hidden = !self.meta.show_hidden;
+                self.meta.reload_suggestions();
+            }
+            Msg::Indexed { index } => {
+                if self.meta.indexing.as_deref() == Some(index.root()) {
+                    self.meta.indexing = None;
+                }
+                self.meta.index = Some(index);
+                self.meta.reload_suggestions();
             }
             Msg::MetaPath { current_path } => {
+                if self.meta.places.visited(Path::new(&current_path)) {
+                    let saved = self.meta.places.save();
+                    self.report_error(saved);
+                }
                 self.meta.path_window.buffer = TextBuffer::from_string(&current_path);
                 self.meta.reload_suggestions();
                 self.tab = Tab::Meta;
             }
             Msg::OpenShell { working_dir } => {
-                let id = self.take_id();
-                let shell = ShellTab::new(
-                    id,
-                    self.atlas.clone(),
-                    self.metrics.clone(),
-                    self.max_dimensions(),
-                    self.sender.clone(),
-                    working_dir,
-                )
-                .unwrap();
-                self.tabs.insert(id, TabType::Shell(shell));
-                self.tab = Tab::Edit(id);
+                self.open_shell(working_dir);
+            }
+            Msg::DismissPrompt => {
+                self.prompt = None;
             }
             Msg::Tab {
                 id,
                 msg: TabMsg::Quit,
             } => {
-                self.tabs.remove(&id);
+                self.close_tab(id);
+            }
+            Msg::Tab {
+                id,
+                msg: TabMsg::Close,
+            } => {
+                let prompt = match self.tabs.get(&id) {
+                    Some(TabType::Text(text_tab)) if text_tab.dirty => {
+                        Some(Prompt::unsaved(id, text_tab))
+                    }
+                    Some(TabType::Shell(shell_tab)) => shell_tab
+                        .running_job()
+                        .map(|job| Prompt::running(id, &shell_tab.title, &job)),
+                    _ => None,
+                };
+                match prompt {
+                    Some(prompt) => self.prompt = Some(prompt),
+                    None => self.close_tab(id),
+                }
             }
             Msg::Tab { id, msg } => {
                 if let Some(tab) = self.tabs.get_mut(&id) {
@@ -1028,7 +2532,7 @@ impl Applet for Editor {
                                 shell_tab.shell_output.frozen_until,
                                 content_buffer,
                             ));
-                            shell_tab.shell_output.undos.clear();
+                            shell_tab.shell_output.undo_tree.clear();
                             shell_tab.shell_output.frozen_until =
                                 add_coord(shell_tab.shell_output.frozen_until, content_size);
 
@@ -1057,19 +2561,54 @@ impl Applet for Editor {
                         (TabMsg::SaveAs { path }, TabType::Text(text_tab)) => {
                             if !path.exists() && path.parent().iter().any(|p| p.is_dir()) {
                                 text_tab.path = Some(path);
-                                // TODO: share this logic!
-                                text_tab.title = text_tab
-                                    .path
-                                    .as_ref()
-                                    .and_then(|p| p.file_name())
-                                    .map(|p| p.to_string_lossy().into_owned())
-                                    .unwrap_or("<unnamed file>".to_string());
-                                let saved = text_tab.save();
+                                text_tab.title = file_title(text_tab.path.as_deref());
+                                let saved = text_tab
+                                    .save(self.config.backup)
+                                    .and_then(|_| text_tab.autosave(id));
                                 if self.report_error(saved).is_some() {
                                     self.tab = Tab::Edit(id)
                                 };
                             }
                         }
+                        (TabMsg::CycleEncoding, TabType::Text(text_tab)) => {
+                            let encoding = text_tab.format.encoding.next();
+                            match &text_tab.path {
+                                // If there are no changes yet, the user probably wants to reread
+                                // the file with a different encoding...
+                                Some(path) if !text_tab.dirty => match fs::read(path) {
+                                    Ok(bytes) => {
+                                        let (format, buffer) =
+                                            FileFormat::decode_as(encoding, &bytes);
+                                        text_tab.stamp = FileStamp::new(path, &bytes).ok();
+                                        text_tab.format = format;
+                                        text_tab.text.buffer = buffer;
+                                        text_tab.text.undo_tree.clear();
+                                    }
+                                    Err(e) => self.error_string = e.to_string(),
+                                },
+                                // ...otherwise, we'll convert it when it's saved.
+                                _ => {
+                                    text_tab.format.encoding = encoding;
+                                    text_tab.format.bom &= !encoding.bom().is_empty();
+                                    text_tab.dirty = true;
+                                }
+                            }
+                        }
+                        (TabMsg::ToggleLineEnding, TabType::Text(text_tab)) => {
+                            text_tab.format.line_ending = text_tab.format.line_ending.toggle();
+                            text_tab.dirty = true;
+                        }
+                        (TabMsg::ToggleBom, TabType::Text(text_tab)) => {
+                            text_tab.format.bom = !text_tab.format.bom;
+                            text_tab.dirty = true;
+                        }
+                        (TabMsg::ToggleWrap, TabType::Text(text_tab)) => {
+                            text_tab.text.toggle_wrap();
+                        }
+                        (TabMsg::Correct { coord, char }, TabType::Text(text_tab)) => {
+                            text_tab.text.correct(coord, char, &mut self.text_stuff);
+                            text_tab.dirty = true;
+                        }
                         (TabMsg::Undo, TabType::Text(text_tab)) => {
                             text_tab.text.undo();
                             text_tab.dirty = true;
@@ -1078,9 +2617,100 @@ impl Applet for Editor {
                             text_tab.text.redo();
                             text_tab.dirty = true;
                         }
+                        (TabMsg::JumpHistory(state), TabType::Text(text_tab)) => {
+                            text_tab.text.jump_to(state);
+                            text_tab.dirty = true;
+                            self.tab = Tab::Edit(id);
+                        }
+                        (TabMsg::ScrollTo { row }, TabType::Text(text_tab)) => {
+                            text_tab.text.scroll_to_row(row);
+                            self.tab = Tab::Edit(id);
+                        }
+                        (TabMsg::GotoLine, TabType::Text(text_tab)) => {
+                            let written = self.goto_window.buffer.content_string();
+                            match written.trim().parse() {
+                                Ok(row) => {
+                                    text_tab.text.scroll_to_row(row);
+                                    self.tab = Tab::Edit(id);
+                                }
+                                Err(_) => {
+                                    self.error_string =
+                                        format!("Error: not a line number: {}", written.trim());
+                                }
+                            }
+                        }
                         (TabMsg::Save, TabType::Text(text_tab)) => {
-                            let result = text_tab.save();
+                            match text_tab.changed_on_disk() {
+                                Ok(true) => {
+                                    self.prompt = Some(Prompt::changed_on_disk(
+                                        id,
+                                        &text_tab.title,
+                                        true,
+                                        false,
+                                    ));
+                                }
+                                Ok(false) => {
+                                    let result = text_tab
+                                        .save(self.config.backup)
+                                        .and_then(|_| text_tab.autosave(id));
+                                    self.report_error(result);
+                                }
+                                Err(e) => self.error_string = format!("Error: {e}"),
+                            }
+                        }
+                        (TabMsg::SaveAndClose, TabType::Text(text_tab)) => {
+                            self.prompt = None;
+                            match text_tab.changed_on_disk() {
+                                Ok(true) => {
+                                    self.prompt = Some(Prompt::changed_on_disk(
+                                        id,
+                                        &text_tab.title,
+                                        true,
+                                        true,
+                                    ));
+                                }
+                                Ok(false) => {
+                                    let result = text_tab.save(self.config.backup);
+                                    if self.report_error(result).is_some() {
+                                        self.close_tab(id);
+                                    }
+                                }
+                                Err(e) => self.error_string = format!("Error: {e}"),
+                            }
+                        }
+                        (TabMsg::Reload, TabType::Text(text_tab)) => {
+                            self.prompt = None;
+                            let result = text_tab.reload();
                             self.report_error(result);
+                            self.tab = Tab::Edit(id);
+                        }
+                        (TabMsg::KeepMine { save, close }, TabType::Text(text_tab)) => {
+                            self.prompt = None;
+                            // The buffer no longer matches what's on disk.
+                            text_tab.dirty = true;
+                            let mut result = text_tab.restamp();
+                            if save {
+                                result = result
+                                    .and_then(|_| text_tab.save(self.config.backup))
+                                    .and_then(|_| text_tab.autosave(id));
+                            }
+                            if self.report_error(result).is_some() && save && close {
+                                self.close_tab(id);
+                            } else {
+                                self.tab = Tab::Edit(id);
+                            }
+                        }
+                        (TabMsg::DiffDisk, TabType::Text(text_tab)) => {
+                            let title = format!("{} (diff)", text_tab.title);
+                            let diff = text_tab.diff_with_disk();
+                            if let Some(diff) = self.report_error(diff) {
+                                let diff_tab = self.new_text_tab(
+                                    None,
+                                    TextBuffer::from_string(&diff),
+                                    FileFormat::default(),
+                                );
+                                diff_tab.title = title;
+                            }
                         }
                         _ => {}
                     }
@@ -1088,15 +2718,95 @@ impl Applet for Editor {
                     // TODO: log?
                 }
             }
-            Msg::SearchResult(id, line) => {
+            Msg::StartGrep { root } => {
+                if root != self.grep.root {
+                    self.grep = GrepResults {
+                        root,
+                        ..GrepResults::default()
+                    };
+                }
+                self.error_string.clear();
+                self.tab = Tab::Grep { offset: 0 };
+            }
+            Msg::RunGrep => {
+                let query = self.grep_window.buffer.content_string();
+                self.grep = grep::grep(&self.grep.root, &query);
+                self.tab = Tab::Grep { offset: 0 };
+            }
+            Msg::OpenAt { path, line } => {
+                let open = self.tabs.iter().find_map(|(id, tab)| match tab {
+                    TabType::Text(text_tab) if text_tab.path.as_ref() == Some(&path) => Some(*id),
+                    _ => None,
+                });
+                let id = match open {
+                    Some(id) => {
+                        self.tab = Tab::Edit(id);
+                        self.check_disk(id);
+                        Some(id)
+                    }
+                    None => {
+                        self.update(Msg::Open { path });
+                        match self.tab {
+                            Tab::Edit(id) => Some(id),
+                            _ => None,
+                        }
+                    }
+                };
+                if let Some(TabType::Text(tab)) = id.and_then(|id| self.tabs.get_mut(&id)) {
+                    tab.text.origin = (line, 0);
+                }
+            }
+            Msg::SearchResult(id, start, end) => {
                 self.tab = Tab::Edit(id);
                 match self.tabs.get_mut(&id).unwrap() {
                     TabType::Text(tab) => {
-                        tab.text.origin = (line, 0);
+                        tab.text.origin = (start.0, 0);
+                        tab.text.select_span(start, end);
                     }
                     TabType::Shell(_) => {}
                 }
             }
+            Msg::SearchOptions(options) => {
+                self.search_options = options;
+                self.search();
+            }
+            Msg::StartReplace => {
+                if let Tab::Search { id, .. } = self.tab {
+                    let query = self.search_window.buffer.content_string();
+                    match self.search_options.matcher(&query) {
+                        Ok(matcher) => {
+                            self.replacing = Some(Replacing {
+                                id,
+                                matcher,
+                                replacement: self.replace_window.buffer.content_string(),
+                                options: self.search_options,
+                                from: (0, 0),
+                                replaced: 0,
+                            });
+                            self.tab = Tab::Edit(id);
+                            self.next_replacement();
+                        }
+                        Err(e) => self.error_string = format!("Error: {e}"),
+                    }
+                }
+            }
+            Msg::Replace(step) => {
+                self.replace_step(step);
+            }
+            Msg::WriteReplacement { ink } => {
+                if let Some(ink_type) =
+                    InkType::classify(&self.metrics, ink, &self.replace_window.selection())
+                {
+                    self.replace_window.ink_row(ink_type, &mut self.text_stuff);
+                }
+            }
+            Msg::EraseReplacement { ink } => {
+                self.replace_window.erase(ink);
+            }
+        }
+
+        if session_changed {
+            self.save_session();
         }
 
         None
@@ -1108,11 +2818,25 @@ impl Applet for Editor {
             Tab::Edit { .. } => "edit",
             Tab::Template => "template",
             Tab::Search { .. } => "search",
+            Tab::History { .. } => "history",
+            Tab::Grep { .. } => "grep",
+            Tab::Goto { .. } => "goto",
         }
     }
 }
 
 fn main() -> anyhow::Result<()> {
+    // Subcommands run headless, so handle them before we grab the screen. Any other arguments
+    // are ignored, as they always have been; some launchers pass their own.
+    if env::args_os().nth(1).is_some_and(|arg| arg == "bench") {
+        let args = clap::Command::new("sill")
+            .subcommand(bench::command())
+            .get_matches();
+        if let Some(args) = args.subcommand_matches("bench") {
+            return bench::run(args);
+        }
+    }
+
     let mut app = app::App::new();
 
     let template_path = BASE_DIRS.place_data_file(TEMPLATE_FILE)?;
@@ -1144,23 +2868,42 @@ fn main() -> anyhow::Result<()> {
         .and_then(|os| full_path(Path::new(&os)))
         .unwrap_or_else(|| "/".to_string());
 
-    let meta = Meta::new(TextWindow::new(
+    let mut path_window = TextWindow::new(
         TextBuffer::from_string(&meta_path),
         atlas.clone(),
         metrics.clone(),
         (1, max_dimensions.1),
-    ));
+    );
+    path_window.allowed_chars = Some(is_path_char);
 
-    let mut search_window = meta.path_window.clone();
+    let autosave_interval = Duration::from_secs(config.autosave_secs);
+
+    let mut search_window = path_window.clone();
     search_window.buffer = TextBuffer::empty();
+    search_window.allowed_chars = None;
+    let grep_window = search_window.clone();
+    let replace_window = search_window.clone();
+    let mut goto_window = search_window.clone();
+    goto_window.allowed_chars = Some(|c| c.is_ascii_digit());
 
     let mut component = Component::with_sender(app.wakeup(), |sender| {
+        if !autosave_interval.is_zero() {
+            let sender = sender.clone();
+            thread::spawn(move || loop {
+                thread::sleep(autosave_interval);
+                sender.send(Msg::Autosave);
+            });
+        }
+
+        let meta = Meta::new(path_window, sender.clone());
         let mut widget = Editor {
             sender,
             template_path,
             metrics: metrics.clone(),
             config,
             error_string: "".to_string(),
+            prompt: None,
+            saved_session: vec![],
             atlas: atlas.clone(),
             tab: Tab::Meta,
             template_offset: 0,
@@ -1168,13 +2911,48 @@ fn main() -> anyhow::Result<()> {
             next_tab_id: 0,
             tabs: BTreeMap::new(),
             search_window,
+            search_options: SearchOptions::default(),
+            replace_window,
+            replacing: None,
+            grep_window,
+            grep: GrepResults::default(),
+            goto_window,
             meta,
         };
 
         let load_result = widget.load_templates();
         widget.report_error(load_result);
+        let load_result = widget.load_language_model();
+        widget.report_error(load_result);
+        if widget.config.record_samples {
+            let sample_log = BASE_DIRS.place_data_file(SAMPLES_FILE).and_then(|path| {
+                // Only for our eyes; it's everything the user has written.
+                fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .mode(0o600)
+                    .open(path)
+            });
+            widget.text_stuff.sample_log = widget.report_error(sample_log);
+        }
+
+        let restored = widget.config.restore_session
+            && match Session::load() {
+                Ok(Some(session)) => widget.restore_session(session),
+                Ok(None) => false,
+                Err(e) => {
+                    widget.error_string = format!("Error: unable to restore session: {e}");
+                    false
+                }
+            };
 
-        widget.new_text_tab(None, TextBuffer::from_string(HELP_TEXT));
+        if !restored {
+            widget.new_text_tab(
+                None,
+                TextBuffer::from_string(HELP_TEXT),
+                FileFormat::default(),
+            );
+        }
 
         widget
     });
@@ -1186,7 +2964,14 @@ fn main() -> anyhow::Result<()> {
 
 #[cfg(test)]
 mod test {
-    use crate::Config;
+    use crate::{parse_stat, Config};
+
+    #[test]
+    fn test_parse_stat() {
+        let stat = "4321 (my (odd) job) S 1234 4321 1234 0 -1 4194304 95 0 0 0";
+        assert_eq!(parse_stat(stat), Some(("my (odd) job", 1234)));
+        assert_eq!(parse_stat("garbage"), None);
+    }
 
     #[test]
     fn test_default_config() {
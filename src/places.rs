@@ -0,0 +1,87 @@
+use crate::util::{now, write_atomic};
+use crate::BASE_DIRS;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::{Path, PathBuf};
+
+const PLACES_FILE: &str = "places.json";
+const NUM_RECENT: usize = 8;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Place {
+    pub path: PathBuf,
+    /// When the path was last opened, in seconds since the epoch.
+    pub opened: u64,
+}
+
+/// Paths the user is likely to want again: recently-opened files, and any files or directories
+/// they've pinned.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Places {
+    /// Most recent first.
+    pub recent: Vec<Place>,
+    pub pinned: Vec<Place>,
+}
+
+impl Places {
+    pub fn load() -> io::Result<Places> {
+        match BASE_DIRS.find_data_file(PLACES_FILE) {
+            Some(path) => Ok(serde_json::from_reader(io::BufReader::new(File::open(
+                path,
+            )?))?),
+            None => Ok(Places::default()),
+        }
+    }
+
+    pub fn save(&self) -> io::Result<()> {
+        let json = serde_json::to_vec(self)?;
+        write_atomic(&BASE_DIRS.place_data_file(PLACES_FILE)?, &json)
+    }
+
+    pub fn is_pinned(&self, path: &Path) -> bool {
+        self.pinned.iter().any(|p| p.path == path)
+    }
+
+    /// Record that a file was just opened.
+    pub fn opened(&mut self, path: &Path) {
+        let opened = now();
+        self.recent.retain(|p| p.path != path);
+        self.recent.insert(
+            0,
+            Place {
+                path: path.to_path_buf(),
+                opened,
+            },
+        );
+        self.recent.truncate(NUM_RECENT);
+        self.visited(path);
+    }
+
+    /// Record that a path was just opened or browsed to, if it's pinned. Returns whether it was,
+    /// and so whether there's anything new to save.
+    pub fn visited(&mut self, path: &Path) -> bool {
+        let opened = now();
+        let mut found = false;
+        for place in &mut self.pinned {
+            if place.path == path {
+                place.opened = opened;
+                found = true;
+            }
+        }
+        found
+    }
+
+    pub fn toggle_pin(&mut self, path: &Path) {
+        if self.is_pinned(path) {
+            self.pinned.retain(|p| p.path != path);
+        } else {
+            // Presumably the user is looking at it right now.
+            self.pinned.push(Place {
+                path: path.to_path_buf(),
+                opened: now(),
+            });
+        }
+    }
+}
@@ -0,0 +1,248 @@
+use crate::util::{content_hash, write_atomic_with};
+use serde::{Deserialize, Serialize};
+use std::fs::Metadata;
+use std::os::unix::fs::{fchown, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use std::{fs, io};
+
+/// Enough to follow any sane chain of links; the kernel's own limit is 40.
+const MAX_LINKS: usize = 40;
+
+/// Whether to keep a copy of a file's previous contents when it's first saved.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Backup {
+    None,
+    /// Copy `file` to `file~`, replacing any older backup.
+    Simple,
+    /// Copy `file` to `file.~N~`, where `N` is one more than the highest existing backup.
+    Numbered,
+}
+
+/// What a file looked like when we last read or wrote it, so we can tell whether it's been
+/// changed by some other program since.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+
+impl FileStamp {
+    /// Stamp a file, given the contents we just read from or wrote to it.
+    pub fn new(path: &Path, contents: &[u8]) -> io::Result<FileStamp> {
+        let metadata = fs::metadata(path)?;
+        Ok(FileStamp {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: content_hash(contents),
+        })
+    }
+
+    /// Whether the file's contents have changed since it was stamped. Just touching the file
+    /// doesn't count, and neither does deleting it, since saving will simply recreate it.
+    pub fn changed(&self, path: &Path) -> io::Result<bool> {
+        let metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(e) => return Err(e),
+        };
+        if metadata.len() != self.len {
+            return Ok(true);
+        }
+        if metadata.modified().ok() == self.modified {
+            return Ok(false);
+        }
+        Ok(content_hash(&fs::read(path)?) != self.hash)
+    }
+}
+
+/// Follow any symlinks to the file they point to, even if that file doesn't exist yet.
+fn resolve_links(path: &Path) -> io::Result<PathBuf> {
+    let mut path = path.to_path_buf();
+    for _ in 0..MAX_LINKS {
+        match fs::read_link(&path) {
+            // NB: if the target is absolute, `join` replaces the path entirely.
+            Ok(target) => {
+                path = match path.parent() {
+                    Some(dir) => dir.join(target),
+                    None => target,
+                }
+            }
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::InvalidInput | io::ErrorKind::NotFound
+                ) =>
+            {
+                return Ok(path);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::other(format!(
+        "too many levels of symbolic links: {}",
+        path.display()
+    )))
+}
+
+/// Parse the `N` out of a backup named `file.~N~`.
+fn backup_number(backup_name: &str, file_name: &str) -> Option<u32> {
+    backup_name
+        .strip_prefix(file_name)?
+        .strip_prefix(".~")?
+        .strip_suffix('~')?
+        .parse()
+        .ok()
+}
+
+fn backup_path(path: &Path, backup: Backup) -> io::Result<Option<PathBuf>> {
+    let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+    match backup {
+        Backup::None => Ok(None),
+        Backup::Simple => Ok(Some(path.with_file_name(format!("{file_name}~")))),
+        Backup::Numbered => {
+            let dir = match path.parent() {
+                Some(dir) if !dir.as_os_str().is_empty() => dir,
+                _ => Path::new("."),
+            };
+            let highest = fs::read_dir(dir)?
+                .filter_map(|entry| entry.ok())
+                .filter_map(|entry| {
+                    let name = entry.file_name();
+                    backup_number(name.to_str()?, &file_name)
+                })
+                .max()
+                .unwrap_or(0);
+            let next = highest + 1;
+            Ok(Some(path.with_file_name(format!("{file_name}.~{next}~"))))
+        }
+    }
+}
+
+/// Give a newly-written file the same mode and owner as the file it's replacing.
+fn copy_attributes(file: &fs::File, original: &Metadata) -> io::Result<()> {
+    let current = file.metadata()?;
+    if (current.uid(), current.gid()) != (original.uid(), original.gid()) {
+        match fchown(file, Some(original.uid()), Some(original.gid())) {
+            // Only root can give files away; if we're not root, we'll have to make do.
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {}
+            other => other?,
+        }
+    }
+    // NB: after the chown, which may clear the setuid and setgid bits.
+    file.set_permissions(original.permissions())
+}
+
+/// Save a file without ever leaving it half-written. Symlinks are followed, so we replace the
+/// file they point to rather than the link itself, and the file keeps its mode and ownership.
+pub fn save_file(path: &Path, contents: &[u8], backup: Backup) -> io::Result<()> {
+    let target = resolve_links(path)?;
+    let original = match fs::metadata(&target) {
+        Ok(metadata) => Some(metadata),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(e),
+    };
+
+    if original.is_some() {
+        if let Some(backup_path) = backup_path(&target, backup)? {
+            fs::copy(&target, backup_path)?;
+        }
+    }
+
+    // A new file gets the usual permissions. An existing one starts out private, and only gets
+    // the original's mode once its owner is right.
+    let mode = if original.is_some() { 0o600 } else { 0o666 };
+    let result = write_atomic_with(&target, contents, mode, |file| match &original {
+        Some(metadata) => copy_attributes(file, metadata),
+        None => Ok(()),
+    });
+
+    result.map_err(|e| match e.kind() {
+        // We may be able to write the file, but not create new files in its directory. Writing
+        // it in place could leave it half-written, so don't; say why instead.
+        io::ErrorKind::PermissionDenied => io::Error::new(
+            e.kind(),
+            format!(
+                "can't save {} safely, since files can't be created in its directory: {e}",
+                target.display()
+            ),
+        ),
+        _ => e,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::Permissions;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn test_backup_number() {
+        assert_eq!(backup_number("notes.txt.~12~", "notes.txt"), Some(12));
+        assert_eq!(backup_number("notes.txt~", "notes.txt"), None);
+        assert_eq!(backup_number("notes.txt.~x~", "notes.txt"), None);
+        assert_eq!(backup_number("other.txt.~1~", "notes.txt"), None);
+    }
+
+    /// A fresh, empty directory to play in.
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("sill-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn mode(path: &Path) -> u32 {
+        fs::metadata(path).unwrap().permissions().mode() & 0o7777
+    }
+
+    #[test]
+    fn test_copy_attributes() {
+        let dir = scratch_dir("copy-attributes");
+        let (original, copy) = (dir.join("original"), dir.join("copy"));
+        fs::write(&original, "").unwrap();
+        fs::set_permissions(&original, Permissions::from_mode(0o640)).unwrap();
+        let file = fs::File::create(&copy).unwrap();
+        copy_attributes(&file, &fs::metadata(&original).unwrap()).unwrap();
+        assert_eq!(mode(&copy), 0o640);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_save_file() {
+        let dir = scratch_dir("save-file");
+        let path = dir.join("notes.txt");
+        fs::write(&path, "old").unwrap();
+        fs::set_permissions(&path, Permissions::from_mode(0o600)).unwrap();
+        save_file(&path, b"new", Backup::Simple).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(fs::read_to_string(dir.join("notes.txt~")).unwrap(), "old");
+
+        // Saving through a link rewrites the file it points to, and leaves the link alone.
+        let link = dir.join("link");
+        std::os::unix::fs::symlink("notes.txt", &link).unwrap();
+        save_file(&link, b"newer", Backup::Numbered).unwrap();
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&path).unwrap(), "newer");
+        assert_eq!(
+            fs::read_to_string(dir.join("notes.txt.~1~")).unwrap(),
+            "new"
+        );
+
+        // No temporary files are left behind.
+        let mut names: Vec<_> = fs::read_dir(&dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["link", "notes.txt", "notes.txt.~1~", "notes.txt~"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
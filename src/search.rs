@@ -0,0 +1,164 @@
+use regex::{Regex, RegexBuilder};
+use std::borrow::Cow;
+use std::ops::Range;
+
+/// Stop after this many matches; there's only so much paging anyone will do.
+const MAX_MATCHES: usize = 1000;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct SearchOptions {
+    /// Treat the query as a regular expression, instead of a literal string.
+    pub regex: bool,
+    pub ignore_case: bool,
+}
+
+impl SearchOptions {
+    pub fn matcher(self, query: &str) -> Result<Regex, String> {
+        let pattern = if self.regex {
+            Cow::Borrowed(query)
+        } else {
+            Cow::Owned(regex::escape(query))
+        };
+        RegexBuilder::new(&pattern)
+            .case_insensitive(self.ignore_case)
+            .multi_line(true)
+            .build()
+            .map_err(|e| {
+                // Syntax errors come with a diagram of the pattern, which won't fit in the footer.
+                let message = e.to_string();
+                match message.lines().rev().find(|l| !l.trim().is_empty()) {
+                    Some(line) => line.trim().to_string(),
+                    None => message,
+                }
+            })
+    }
+}
+
+/// The byte ranges of every non-empty match of the query in the text.
+pub fn find_all(
+    text: &str,
+    query: &str,
+    options: SearchOptions,
+) -> Result<Vec<Range<usize>>, String> {
+    if query.is_empty() {
+        return Ok(vec![]);
+    }
+    let matcher = options.matcher(query)?;
+    Ok(matcher
+        .find_iter(text)
+        .filter(|m| !m.as_str().is_empty())
+        .take(MAX_MATCHES)
+        .map(|m| m.range())
+        .collect())
+}
+
+/// The first non-empty match that starts at or after the given byte index.
+pub fn next_match(matcher: &Regex, text: &str, from: usize) -> Option<Range<usize>> {
+    let mut at = from;
+    while at <= text.len() {
+        let found = matcher.find_at(text, at)?;
+        if !found.as_str().is_empty() {
+            return Some(found.range());
+        }
+        // Step over the empty match, keeping to char boundaries.
+        at = found.end()
+            + text[found.end()..]
+                .chars()
+                .next()
+                .map_or(1, |c| c.len_utf8());
+    }
+    None
+}
+
+/// The text to replace the match at the given range with. For regex searches, the replacement
+/// can refer to capture groups, like `$1` or `${name}`; otherwise it's used as-is.
+pub fn replacement(
+    matcher: &Regex,
+    text: &str,
+    range: Range<usize>,
+    template: &str,
+    options: SearchOptions,
+) -> String {
+    if !options.regex {
+        return template.to_string();
+    }
+    let mut result = String::new();
+    if let Some(captures) = matcher.captures_at(text, range.start) {
+        captures.expand(template, &mut result);
+    }
+    result
+}
+
+/// Every match at or after the given byte index, along with what to replace it with.
+pub fn replace_all(
+    matcher: &Regex,
+    text: &str,
+    from: usize,
+    template: &str,
+    options: SearchOptions,
+) -> Vec<(Range<usize>, String)> {
+    let mut replacements = vec![];
+    let mut at = from;
+    while let Some(range) = next_match(matcher, text, at) {
+        at = range.end;
+        let replaced = replacement(matcher, text, range.clone(), template, options);
+        replacements.push((range, replaced));
+    }
+    replacements
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_all() {
+        let text = "Let it be.\nlet it go?\n";
+        let literal = SearchOptions::default();
+        assert_eq!(find_all(text, "let", literal).unwrap(), vec![11..14]);
+        assert_eq!(find_all(text, ".", literal).unwrap(), vec![9..10]);
+
+        let ignore_case = SearchOptions {
+            ignore_case: true,
+            ..literal
+        };
+        assert_eq!(find_all(text, "LET", ignore_case).unwrap(), [0..3, 11..14]);
+
+        let regex = SearchOptions {
+            regex: true,
+            ..literal
+        };
+        assert_eq!(find_all(text, r"it \w+.$", regex).unwrap(), [4..10, 15..21]);
+        assert!(find_all(text, "^", regex).unwrap().is_empty());
+        assert!(find_all(text, "(it", regex).is_err());
+    }
+
+    #[test]
+    fn test_replace() {
+        let text = "let x = 1;\nlet y = 22;\n";
+        let regex = SearchOptions {
+            regex: true,
+            ..SearchOptions::default()
+        };
+        let matcher = regex.matcher(r"let (\w+) = (?P<value>\d+)").unwrap();
+        assert_eq!(next_match(&matcher, text, 0), Some(0..9));
+        assert_eq!(next_match(&matcher, text, 1), Some(11..21));
+        assert_eq!(next_match(&matcher, text, 12), None);
+
+        let replaced: Vec<String> =
+            replace_all(&matcher, text, 0, "const $1: i32 = ${value}", regex)
+                .into_iter()
+                .map(|(_, r)| r)
+                .collect();
+        assert_eq!(replaced, ["const x: i32 = 1", "const y: i32 = 22"]);
+
+        let literal = SearchOptions::default();
+        let matcher = literal.matcher("$").unwrap();
+        let all = replace_all(&matcher, "a$b$", 0, "$1", literal);
+        assert_eq!(all, [(1..2, "$1".to_string()), (3..4, "$1".to_string())]);
+
+        // Empty matches are skipped, even next to multi-byte chars.
+        let matcher = regex.matcher("é*").unwrap();
+        assert_eq!(next_match(&matcher, "aaé", 0), Some(2..4));
+    }
+}
@@ -0,0 +1,50 @@
+use crate::util::write_atomic;
+use crate::{Coord, Selection, BASE_DIRS};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io;
+use std::path::PathBuf;
+
+const SESSION_FILE: &str = "session.json";
+
+#[derive(Serialize, Deserialize)]
+pub enum SessionTab {
+    Text {
+        path: PathBuf,
+        origin: Coord,
+        selection: Selection<Coord>,
+        #[serde(default)]
+        wrap: bool,
+    },
+    Shell {
+        working_dir: PathBuf,
+    },
+}
+
+/// The open tabs, so we can pick up where we left off next time. Unsaved changes aren't stored
+/// here; they're handled by swap files.
+#[derive(Serialize, Deserialize, Default)]
+pub struct Session {
+    pub tabs: Vec<SessionTab>,
+    /// The index of the tab that was being edited, if any.
+    pub active: Option<usize>,
+}
+
+impl Session {
+    pub fn load() -> io::Result<Option<Session>> {
+        let path = match BASE_DIRS.find_state_file(SESSION_FILE) {
+            Some(path) => path,
+            None => return Ok(None),
+        };
+        let session = serde_json::from_reader(io::BufReader::new(File::open(path)?))?;
+        Ok(Some(session))
+    }
+
+    pub fn to_json(&self) -> io::Result<Vec<u8>> {
+        Ok(serde_json::to_vec(self)?)
+    }
+
+    pub fn save(json: &[u8]) -> io::Result<()> {
+        write_atomic(&BASE_DIRS.place_state_file(SESSION_FILE)?, json)
+    }
+}
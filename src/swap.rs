@@ -0,0 +1,133 @@
+use crate::undo_tree::UndoTree;
+use crate::util::{now, write_atomic};
+use crate::{FileFormat, TextBuffer, BASE_DIRS};
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::{fs, io, process};
+use uuid::Uuid;
+
+const SWAP_DIR: &str = "swap";
+
+/// Pids are reused, especially across reboots, so we also tag our swap files with a unique id.
+/// That tells our own swap files apart from ones left by an earlier process with the same pid.
+static SESSION: Lazy<String> = Lazy::new(|| Uuid::new_v4().to_simple().to_string());
+
+/// A snapshot of a buffer with unsaved changes, so the changes can be recovered if sill dies
+/// before they're saved.
+///
+/// Swap files are named after the process and tab that wrote them. Any swap file written by a
+/// sill process that's no longer running is an orphan, and offered for recovery on the meta tab.
+#[derive(Serialize, Deserialize)]
+pub struct Swap {
+    pub title: String,
+    pub path: Option<PathBuf>,
+    pub format: FileFormat,
+    /// When the snapshot was taken, in seconds since the epoch.
+    pub time: u64,
+    pub content: TextBuffer,
+    /// So edits made before the crash can still be undone. Older swap files don't have one.
+    #[serde(default)]
+    pub undo_tree: UndoTree,
+}
+
+impl Swap {
+    pub fn new(
+        title: String,
+        path: Option<PathBuf>,
+        format: FileFormat,
+        content: TextBuffer,
+        undo_tree: UndoTree,
+    ) -> Swap {
+        Swap {
+            title,
+            path,
+            format,
+            time: now(),
+            content,
+            undo_tree,
+        }
+    }
+}
+
+fn swap_file(tab_id: usize) -> io::Result<PathBuf> {
+    let pid = process::id();
+    let session = &*SESSION;
+    BASE_DIRS.place_state_file(Path::new(SWAP_DIR).join(format!("{pid}-{session}-{tab_id}.json")))
+}
+
+pub fn write(tab_id: usize, swap: &Swap) -> io::Result<()> {
+    let json = serde_json::to_vec(swap)?;
+    write_atomic(&swap_file(tab_id)?, &json)
+}
+
+pub fn remove(tab_id: usize) -> io::Result<()> {
+    discard(&swap_file(tab_id)?)
+}
+
+/// Remove a swap file, which is not an error if it's already gone.
+pub fn discard(file: &Path) -> io::Result<()> {
+    match fs::remove_file(file) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Whether the process is a running copy of sill. If the pid has been reused by some other
+/// program, the sill that wrote the swap file is long gone.
+fn is_running_sill(pid: u32) -> bool {
+    let proc_dir = PathBuf::from(format!("/proc/{pid}"));
+    match (fs::read_link(proc_dir.join("exe")), std::env::current_exe()) {
+        (Ok(theirs), Ok(ours)) => same_exe(&theirs, &ours),
+        // We may not be allowed to look; assume the worst, and leave its files alone.
+        _ => proc_dir.exists(),
+    }
+}
+
+/// Whether two links from `/proc/<pid>/exe` name the same program. Once a running binary has
+/// been replaced, say by an upgrade, the kernel reports its old path with " (deleted)" tacked on.
+fn same_exe(a: &Path, b: &Path) -> bool {
+    let strip = |p: &Path| {
+        let p = p.as_os_str().to_string_lossy().into_owned();
+        match p.strip_suffix(" (deleted)") {
+            Some(stripped) => stripped.to_string(),
+            None => p,
+        }
+    };
+    strip(a) == strip(b)
+}
+
+/// Find the swap files left behind by processes that are no longer running, oldest first.
+pub fn orphans() -> Vec<(PathBuf, Swap)> {
+    let mut result: Vec<(PathBuf, Swap)> = BASE_DIRS
+        .list_state_files(SWAP_DIR)
+        .into_iter()
+        .filter(|file| {
+            let name = file.file_name().and_then(|name| name.to_str());
+            let mut parts = name.into_iter().flat_map(|name| name.split('-'));
+            let pid = parts.next().and_then(|pid| pid.parse::<u32>().ok());
+            let session = parts.next();
+            match (pid, session) {
+                (Some(pid), Some(session)) if session != SESSION.as_str() => {
+                    pid == process::id() || !is_running_sill(pid)
+                }
+                _ => false,
+            }
+        })
+        .filter_map(|file| {
+            let read = File::open(&file).and_then(|f| {
+                serde_json::from_reader(io::BufReader::new(f)).map_err(io::Error::from)
+            });
+            match read {
+                Ok(swap) => Some((file, swap)),
+                Err(e) => {
+                    eprintln!("Unable to read swap file {}: {e}", file.display());
+                    None
+                }
+            }
+        })
+        .collect();
+    result.sort_by_key(|(_, swap)| swap.time);
+    result
+}
@@ -1,3 +1,6 @@
+use serde::{Deserialize, Serialize};
+use std::rc::Rc;
+
 type Coord = (usize, usize);
 
 pub fn add_coord(a: Coord, b: Coord) -> Coord {
@@ -17,9 +20,20 @@ pub fn diff_coord(a: Coord, b: Coord) -> Coord {
     }
 }
 
-#[derive(Clone)]
+/// Lines are grouped into shared chunks of at most this many lines. An edit only needs to copy the
+/// chunk it touches, and splitting or joining buffers moves whole chunks instead of single lines.
+const CHUNK_LINES: usize = 512;
+
+/// A sequence of lines, stored as a shallow rope: a list of reference-counted chunks of lines.
+/// This keeps edits cheap on large files, and makes copying out large spans of text (eg. for the
+/// undo history) mostly a matter of bumping reference counts.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
 pub struct TextBuffer {
-    pub contents: Vec<Vec<char>>,
+    /// Never empty, and none of the chunks are empty either.
+    chunks: Vec<Rc<Vec<Vec<char>>>>,
+    /// The row of the first line in each chunk, followed by the total number of lines.
+    starts: Vec<usize>,
 }
 
 impl Default for TextBuffer {
@@ -30,20 +44,70 @@ impl Default for TextBuffer {
 
 impl TextBuffer {
     pub fn empty() -> TextBuffer {
-        TextBuffer {
-            contents: vec![vec![]],
+        TextBuffer::from_lines(vec![vec![]])
+    }
+
+    fn from_lines(lines: Vec<Vec<char>>) -> TextBuffer {
+        let mut chunks = vec![];
+        let mut iter = lines.into_iter().peekable();
+        while iter.peek().is_some() {
+            let chunk: Vec<_> = iter.by_ref().take(CHUNK_LINES).collect();
+            chunks.push(Rc::new(chunk));
+        }
+        if chunks.is_empty() {
+            chunks.push(Rc::new(vec![vec![]]));
         }
+        let mut buffer = TextBuffer {
+            chunks,
+            starts: vec![],
+        };
+        buffer.reindex();
+        buffer
     }
 
     pub fn from_string(str: &str) -> TextBuffer {
-        let contents: Vec<_> = str.split('\n').map(|line| line.chars().collect()).collect();
-        TextBuffer { contents }
+        TextBuffer::from_lines(str.split('\n').map(|line| line.chars().collect()).collect())
     }
 
     pub fn padding(coord: Coord) -> TextBuffer {
         let mut contents = vec![vec![]; coord.0];
         contents.push(vec![' '; coord.1]);
-        TextBuffer { contents }
+        TextBuffer::from_lines(contents)
+    }
+
+    fn reindex(&mut self) {
+        self.starts.clear();
+        let mut start = 0;
+        for chunk in &self.chunks {
+            self.starts.push(start);
+            start += chunk.len();
+        }
+        self.starts.push(start);
+    }
+
+    /// Find the chunk containing a particular row, and the row's offset within that chunk.
+    fn locate(&self, row: usize) -> (usize, usize) {
+        let chunk = self.starts.partition_point(|start| *start <= row) - 1;
+        (chunk, row - self.starts[chunk])
+    }
+
+    /// The number of lines in the buffer. Always at least one, even for an empty buffer.
+    pub fn line_count(&self) -> usize {
+        *self.starts.last().expect("starts is never empty")
+    }
+
+    pub fn line(&self, row: usize) -> Option<&[char]> {
+        if row >= self.line_count() {
+            return None;
+        }
+        let (chunk, offset) = self.locate(row);
+        Some(&self.chunks[chunk][offset])
+    }
+
+    pub fn lines(&self) -> impl Iterator<Item = &[char]> + '_ {
+        self.chunks
+            .iter()
+            .flat_map(|chunk| chunk.iter().map(|line| line.as_slice()))
     }
 
     /// Given a coordinate, find the nearest valid coordinate in the text.
@@ -51,33 +115,54 @@ impl TextBuffer {
     /// and rows past the end clamp to the lasts valid coordinate.
     pub fn clamp(&self, coords: Coord) -> Coord {
         let (row, col) = coords;
-        let rows = self.contents.len();
-        if row >= rows {
-            let row = rows - 1;
-            (row, self.contents[row].len())
-        } else {
-            let cols = self.contents[row].len();
-            (row, cols.min(col))
+        match self.line(row) {
+            Some(line) => (row, line.len().min(col)),
+            None => self.end(),
         }
     }
 
     pub fn split_off(&mut self, at: Coord) -> TextBuffer {
         let (row, col) = self.clamp(at);
-        let insert_row = &mut self.contents[row];
-        let trailer = insert_row.split_off(col);
-        let mut result = Vec::with_capacity(self.contents.len() - row);
-        result.push(trailer);
-        result.extend(self.contents.drain((row + 1)..));
-        TextBuffer { contents: result }
+        let (chunk_index, offset) = self.locate(row);
+        let chunk = Rc::make_mut(&mut self.chunks[chunk_index]);
+        let trailer = chunk[offset].split_off(col);
+        let mut first = Vec::with_capacity(chunk.len() - offset);
+        first.push(trailer);
+        first.extend(chunk.drain((offset + 1)..));
+
+        let mut chunks = Vec::with_capacity(self.chunks.len() - chunk_index);
+        chunks.push(Rc::new(first));
+        chunks.extend(self.chunks.drain((chunk_index + 1)..));
+        self.reindex();
+
+        let mut result = TextBuffer {
+            chunks,
+            starts: vec![],
+        };
+        result.reindex();
+        result
     }
 
     pub fn append(&mut self, buffer: TextBuffer) {
-        let mut iter = buffer.contents.into_iter();
-        self.contents
+        let mut iter = buffer.chunks.into_iter();
+        let mut first = iter.next().expect("buffers always have a chunk");
+        let first_lines = Rc::make_mut(&mut first);
+        let joined = first_lines.remove(0);
+        Rc::make_mut(self.chunks.last_mut().expect("buffers always have a chunk"))
             .last_mut()
-            .unwrap()
-            .extend(iter.next().unwrap());
-        self.contents.extend(iter);
+            .expect("chunks are never empty")
+            .extend(joined);
+
+        // Merge small chunks at the seam, so repeated edits don't fragment the buffer.
+        let last = self.chunks.last_mut().expect("buffers always have a chunk");
+        if last.len() + first_lines.len() <= CHUNK_LINES {
+            Rc::make_mut(last).append(first_lines);
+        }
+        if !first.is_empty() {
+            self.chunks.push(first);
+        }
+        self.chunks.extend(iter);
+        self.reindex();
     }
 
     pub fn replace(&mut self, replace: Replace) -> Replace {
@@ -108,30 +193,22 @@ impl TextBuffer {
 
     pub fn copy(&self, from: Coord, until: Coord) -> TextBuffer {
         assert!(from <= until);
-        let (from_row, from_col) = self.clamp(from);
-        let (until_row, until_col) = self.clamp(until);
-
-        let contents = if from_row == until_row {
-            vec![self.contents[from_row][from_col..until_col].to_vec()]
-        } else {
-            let mut contents = vec![self.contents[from_row][from_col..].to_vec()];
-            contents.extend(self.contents[(from_row + 1)..until_row].iter().cloned());
-            contents.push(self.contents[until_row][..until_col].to_vec());
-            contents
-        };
-
-        TextBuffer { contents }
+        // Cloning only copies the list of chunks; the split then copies at most one chunk.
+        let mut copy = self.clone();
+        copy.split_off(until);
+        copy.split_off(from)
     }
 
     pub fn end(&self) -> Coord {
-        let row = self.contents.len() - 1;
-        (row, self.contents[row].len())
+        let row = self.line_count() - 1;
+        let last = self.chunks.last().expect("buffers always have a chunk");
+        (row, last.last().expect("chunks are never empty").len())
     }
 
     /// Render the contents of the buffer as a new String.
     pub fn content_string(&self) -> String {
         let mut result = String::new();
-        for (i, line) in self.contents.iter().enumerate() {
+        for (i, line) in self.lines().enumerate() {
             if i != 0 {
                 result.push('\n');
             }
@@ -141,7 +218,19 @@ impl TextBuffer {
     }
 }
 
-#[derive(Clone)]
+impl From<String> for TextBuffer {
+    fn from(string: String) -> Self {
+        TextBuffer::from_string(&string)
+    }
+}
+
+impl From<TextBuffer> for String {
+    fn from(buffer: TextBuffer) -> Self {
+        buffer.content_string()
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Replace {
     pub from: Coord,
     pub until: Coord,
@@ -191,8 +280,12 @@ impl IndexedString {
         IndexedString { string, line_ends }
     }
 
+    fn line_start(&self, line_no: usize) -> usize {
+        line_no.checked_sub(1).map_or(0, |i| self.line_ends[i])
+    }
+
     pub fn line(&self, line_no: usize) -> &str {
-        let start = line_no.checked_sub(1).map_or(0, |i| self.line_ends[i]);
+        let start = self.line_start(line_no);
         let end = self
             .line_ends
             .get(line_no)
@@ -207,6 +300,24 @@ impl IndexedString {
             Err(i) => i,
         }
     }
+
+    /// The byte index of the char at the given row and column, clamped to the end of the line.
+    pub fn index_of(&self, (row, col): Coord) -> usize {
+        if row > self.line_ends.len() {
+            return self.string.len();
+        }
+        let line = self.line(row);
+        let line = line.strip_suffix('\n').unwrap_or(line);
+        let offset = line.char_indices().nth(col).map_or(line.len(), |(i, _)| i);
+        self.line_start(row) + offset
+    }
+
+    /// The row and column of the char at the given byte index.
+    pub fn coord(&self, index: usize) -> Coord {
+        let row = self.line_number(index);
+        let col = self.string[self.line_start(row)..index].chars().count();
+        (row, col)
+    }
 }
 
 #[cfg(test)]
@@ -234,4 +345,54 @@ mod tests {
                 .as_str()
         );
     }
+
+    #[test]
+    fn test_serialize_replace() {
+        let replace = Replace::splice((1, 2), TextBuffer::from_string("two\nlines"));
+        let json = serde_json::to_string(&replace).unwrap();
+        assert_eq!(
+            json,
+            r#"{"from":[1,2],"until":[1,2],"content":"two\nlines"}"#
+        );
+        let parsed: Replace = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.content.line_count(), 2);
+    }
+
+    #[test]
+    fn test_replace_across_chunks() {
+        let text: String = (0..(CHUNK_LINES * 3))
+            .map(|i| format!("line {i}\n"))
+            .collect();
+        let mut buffer = TextBuffer::from_string(&text);
+        let mut lines: Vec<String> = text.split('\n').map(|s| s.to_string()).collect();
+
+        // Join the lines on either side of a chunk boundary, then split them apart again.
+        let row = CHUNK_LINES - 1;
+        let col = lines[row].len();
+        let undo = buffer.replace(Replace::remove((row, col), (row + 1, 0)));
+        let next = lines.remove(row + 1);
+        lines[row].push_str(&next);
+        assert_eq!(buffer.content_string(), lines.join("\n"));
+        assert_eq!(buffer.line_count(), lines.len());
+
+        buffer.replace(undo);
+        assert_eq!(buffer.content_string(), text);
+
+        let copied = buffer.copy((1, 2), (CHUNK_LINES * 2, 3));
+        assert_eq!(copied.line_count(), CHUNK_LINES * 2);
+        assert_eq!(copied.line(0), Some(&['n', 'e', ' ', '1'][..]));
+        assert_eq!(buffer.clamp((CHUNK_LINES * 5, 0)), buffer.end());
+    }
+
+    #[test]
+    fn test_indexed_coord() {
+        let indexed = IndexedString::index("naïve\ncafé au lait\n".to_string());
+        assert_eq!(indexed.coord(0), (0, 0));
+        assert_eq!(indexed.coord("naïve".len()), (0, 5));
+        assert_eq!(indexed.coord("naïve\n".len()), (1, 0));
+        assert_eq!(indexed.coord("naïve\ncafé ".len()), (1, 5));
+        assert_eq!(indexed.index_of((1, 5)), "naïve\ncafé ".len());
+        assert_eq!(indexed.index_of((0, 20)), "naïve".len());
+        assert_eq!(indexed.index_of((5, 0)), indexed.as_str().len());
+    }
 }
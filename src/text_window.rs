@@ -1,8 +1,10 @@
+use crate::undo_tree::UndoTree;
 use crate::util::rotate_queue;
 use crate::*;
 use armrest::dollar::Points;
 use armrest::ink::Ink;
 use armrest::ui::{View, Widget};
+use serde::{Deserialize, Serialize};
 use std::cmp::Ordering;
 use std::collections::{BTreeSet, VecDeque};
 use std::mem;
@@ -11,7 +13,10 @@ use textwrap;
 use textwrap::Options;
 
 const NUM_RECENT_RECOGNITIONS: usize = 10;
+/// How many alternatives the correction strip offers for each recognized char.
+const NUM_ALTERNATIVES: usize = 4;
 const NUM_UNDOS: usize = 64;
+const SUMMARY_CHARS: usize = 24;
 
 pub enum TextMessage {
     Write(Ink),
@@ -25,11 +30,31 @@ pub struct Carat {
     pub ink: Ink,
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
 pub enum Selection<T = Carat> {
     Normal,
-    Single { carat: T },
-    Range { start: T, end: T },
+    Single {
+        carat: T,
+    },
+    Range {
+        start: T,
+        end: T,
+    },
+    /// A rectangle of cells, with the two carats at opposite corners.
+    Block {
+        start: T,
+        end: T,
+    },
+}
+
+/// The rows and columns covered by a block selection: `top..=bottom` and `left..right`.
+fn block_bounds(start: Coord, end: Coord) -> (usize, usize, usize, usize) {
+    (
+        start.0.min(end.0),
+        start.0.max(end.0),
+        start.1.min(end.1),
+        start.1.max(end.1),
+    )
 }
 
 impl<T> Default for Selection<T> {
@@ -43,7 +68,53 @@ pub struct Recognition {
     coord: Coord,
     ink: Ink,
     recognized_as: char,
-    overwrites: Vec<Ink>,
+    /// What the ink was first recognized as, before any corrections.
+    guessed: char,
+    /// The recognizer's best guesses, best first; `recognized_as` is one of these.
+    candidates: Vec<char>,
+    /// Earlier inks in the same cell, and what each was recognized as.
+    overwrites: Vec<(Ink, char)>,
+}
+
+/// The edits made so far in an in-progress transaction.
+#[derive(Clone, Default)]
+struct Transaction {
+    undos: Vec<Replace>,
+    inserted: String,
+    removed: String,
+}
+
+impl Transaction {
+    fn record(&mut self, undo: Replace, buffer: &TextBuffer) {
+        fn preview(summary: &mut String, buffer: &TextBuffer) {
+            for (i, line) in buffer.lines().enumerate() {
+                if i != 0 {
+                    summary.push('⏎');
+                }
+                summary.extend(line.iter().take(SUMMARY_CHARS));
+                if summary.chars().count() >= SUMMARY_CHARS {
+                    break;
+                }
+            }
+        }
+        preview(&mut self.inserted, &buffer.copy(undo.from, undo.until));
+        preview(&mut self.removed, &undo.content);
+        self.undos.push(undo);
+    }
+
+    /// A short description of the edit, for the history tab.
+    fn summary(&self) -> String {
+        let (row, col) = self.undos.first().map_or((0, 0), |u| u.from);
+        let mut summary = format!("{row}:{col}");
+        for (sign, text) in [('+', &self.inserted), ('-', &self.removed)] {
+            if !text.is_empty() {
+                summary.push(' ');
+                summary.push(sign);
+                summary.extend(text.chars().take(SUMMARY_CHARS));
+            }
+        }
+        summary
+    }
 }
 
 #[derive(Clone)]
@@ -54,10 +125,24 @@ pub struct TextWindow {
     pub(crate) selection: Selection,
     pub dimensions: Coord,
     pub origin: Coord,
+    /// Wrap long lines onto several rows of the grid, instead of cutting them off at the edge.
+    /// This only changes what's shown; the buffer itself is left as-is.
+    pub wrap: bool,
+    /// If set, only chars that pass this check are recognized; handy for fields that can only
+    /// hold certain kinds of text, like paths or numbers.
+    pub allowed_chars: Option<fn(char) -> bool>,
+    /// Whether what's written here may be kept as a handwriting sample, if the user asked for
+    /// that. Off for windows that may hold secrets, like a shell's password prompt.
+    pub record_samples: bool,
     pub frozen_until: Coord,
-    pub undos: VecDeque<Replace>,
-    pub redos: Vec<Replace>,
+    /// Edits are undone and redone in groups; see `transaction`.
+    pub undo_tree: UndoTree,
+    pub undo_limit: usize,
+    transaction: Option<Transaction>,
     tentative_recognitions: VecDeque<Recognition>,
+    /// How many of the tentative recognitions came from the most recent ink; these are the ones
+    /// offered in the correction strip.
+    last_recognized: usize,
 }
 
 impl TextWindow {
@@ -74,22 +159,96 @@ impl TextWindow {
             selection: Selection::Normal,
             dimensions,
             origin: (0, 0),
+            wrap: false,
+            allowed_chars: None,
+            record_samples: true,
             frozen_until: (0, 0),
-            undos: VecDeque::new(),
-            redos: vec![],
+            undo_tree: UndoTree::default(),
+            undo_limit: NUM_UNDOS,
+            transaction: None,
             tentative_recognitions: VecDeque::new(),
+            last_recognized: 0,
         }
     }
 
-    pub fn selection(&self) -> Selection<Coord> {
-        let onscreen = |(row, col): Coord| {
-            let (o_row, o_col) = self.origin;
-            if row >= o_row && col >= o_col {
-                Some((row - o_row, col - o_col))
+    fn line_len(&self, row: usize) -> usize {
+        self.buffer.line(row).map_or(0, |l| l.len())
+    }
+
+    /// When wrapping, the start of the screen row that a coordinate falls on. Coordinates past
+    /// the end of a line belong to its last row.
+    fn wrapped_row_start(&self, (row, col): Coord) -> Coord {
+        let cols = self.dimensions.1.max(1);
+        (row, col.min(self.line_len(row)) / cols * cols)
+    }
+
+    /// Given the buffer coordinate at the start of a screen row, the one at the start of the next.
+    fn next_screen_row(&self, (row, col): Coord) -> Coord {
+        let cols = self.dimensions.1.max(1);
+        if !self.wrap {
+            (row + 1, col)
+        } else if col + cols <= self.line_len(row) {
+            // There's always room for the end-of-line marker, so a line that exactly fills its
+            // last row spills over onto another.
+            (row, col + cols)
+        } else {
+            (row + 1, 0)
+        }
+    }
+
+    fn previous_screen_row(&self, (row, col): Coord) -> Coord {
+        if !self.wrap {
+            (row.saturating_sub(1), col)
+        } else if col > 0 {
+            (row, col.saturating_sub(self.dimensions.1.max(1)))
+        } else if row > 0 {
+            self.wrapped_row_start((row - 1, usize::MAX))
+        } else {
+            (0, 0)
+        }
+    }
+
+    /// The buffer coordinate at the start of each row of the grid.
+    pub fn screen_rows(&self) -> Vec<Coord> {
+        let mut rows = Vec::with_capacity(self.dimensions.0);
+        let mut start = self.origin;
+        for _ in 0..self.dimensions.0 {
+            rows.push(start);
+            start = self.next_screen_row(start);
+        }
+        rows
+    }
+
+    /// Where a buffer coordinate appears on the grid, or `None` if it's above or to the left of
+    /// the window. Coordinates below or to the right are returned anyway, out of bounds.
+    fn onscreen(&self, coord: Coord) -> Option<Coord> {
+        let (o_row, o_col) = self.origin;
+        if !self.wrap {
+            return if coord.0 >= o_row && coord.1 >= o_col {
+                Some((coord.0 - o_row, coord.1 - o_col))
             } else {
                 None
+            };
+        }
+        let target = self.wrapped_row_start(coord);
+        if target < self.origin {
+            return None;
+        }
+        let mut start = self.origin;
+        let mut screen_row = 0;
+        while start < target {
+            if screen_row > self.dimensions.0 {
+                // Definitely offscreen, so there's no need to count every row.
+                return Some((screen_row + target.0 - start.0, coord.1 - target.1));
             }
-        };
+            start = self.next_screen_row(start);
+            screen_row += 1;
+        }
+        Some((screen_row, coord.1 - target.1))
+    }
+
+    pub fn selection(&self) -> Selection<Coord> {
+        let onscreen = |coord| self.onscreen(coord);
 
         match &self.selection {
             Selection::Normal => Selection::Normal,
@@ -103,10 +262,96 @@ impl TextWindow {
                 (Some(carat), None) => Selection::Single { carat },
                 (None, None) => Selection::Normal,
             },
+            Selection::Block { start, end } => match (onscreen(start.coord), onscreen(end.coord)) {
+                (Some(start), Some(end)) => Selection::Block { start, end },
+                (None, Some(carat)) => Selection::Single { carat },
+                (Some(carat), None) => Selection::Single { carat },
+                (None, None) => Selection::Normal,
+            },
+        }
+    }
+
+    /// The selection in buffer coordinates, without the ink that made it.
+    pub fn selection_coords(&self) -> Selection<Coord> {
+        match &self.selection {
+            Selection::Normal => Selection::Normal,
+            Selection::Single { carat } => Selection::Single { carat: carat.coord },
+            Selection::Range { start, end } => Selection::Range {
+                start: start.coord,
+                end: end.coord,
+            },
+            Selection::Block { start, end } => Selection::Block {
+                start: start.coord,
+                end: end.coord,
+            },
         }
     }
 
+    /// Select the given coordinates, as if the user had drawn carats there.
+    pub fn set_selection(&mut self, selection: Selection<Coord>) {
+        let carat = |coord| {
+            // A plain vertical line on the left edge of the cell.
+            let mut ink = Ink::new();
+            ink.push(0.0, 0.0, 0.0);
+            ink.push(0.0, self.grid_metrics.height as f32, 0.1);
+            ink.pen_up();
+            Carat {
+                coord: self.buffer.clamp(coord),
+                ink,
+            }
+        };
+        self.selection = match selection {
+            Selection::Normal => Selection::Normal,
+            Selection::Single { carat: coord } => Selection::Single {
+                carat: carat(coord),
+            },
+            Selection::Range { start, end } => Selection::Range {
+                start: carat(start),
+                end: carat(end),
+            },
+            Selection::Block { start, end } => Selection::Block {
+                start: carat(start),
+                end: carat(end),
+            },
+        };
+    }
+
+    /// Select a span of the buffer, scrolling as little as possible to bring it onscreen.
+    pub fn select_span(&mut self, start: Coord, end: Coord) {
+        self.set_selection(Selection::Range { start, end });
+        // NB: try and get both start/end onscreen where possible.
+        self.scroll_into_view(end);
+        self.scroll_into_view(start);
+    }
+
+    /// Scroll to the start of the given row, without scrolling the last line of the buffer any
+    /// higher than the bottom of the window.
+    pub fn scroll_to_row(&mut self, row: usize) {
+        let last_page = self
+            .buffer
+            .line_count()
+            .saturating_sub(self.dimensions.0.max(1));
+        self.origin = (row.min(last_page), 0);
+    }
+
+    pub fn toggle_wrap(&mut self) {
+        self.wrap = !self.wrap;
+        self.origin = (self.origin.0, 0);
+    }
+
     pub fn page_relative(&mut self, (row_d, col_d): (isize, isize)) {
+        if self.wrap {
+            // Everything fits horizontally, so only page up and down, a screen row at a time.
+            let stride = (self.dimensions.0 as isize - 5).max(1) * row_d;
+            for _ in 0..stride.unsigned_abs() {
+                self.origin = if stride > 0 {
+                    self.next_screen_row(self.origin)
+                } else {
+                    self.previous_screen_row(self.origin)
+                };
+            }
+            return;
+        }
         let (row, col) = &mut self.origin;
         fn page_round(current: usize, delta: isize, size: usize) -> usize {
             // It's useful to stride less than a whole page, to preserve some context.
@@ -119,6 +364,23 @@ impl TextWindow {
     }
 
     pub fn scroll_into_view(&mut self, coord: Coord) {
+        if self.wrap {
+            let target = self.wrapped_row_start(coord);
+            if target < self.origin {
+                self.origin = target;
+            } else if self
+                .onscreen(coord)
+                .is_some_and(|(row, _)| row >= self.dimensions.0)
+            {
+                // Scroll down just far enough to put it on the bottom row.
+                let mut origin = target;
+                for _ in 1..self.dimensions.0 {
+                    origin = self.previous_screen_row(origin);
+                }
+                self.origin = origin;
+            }
+            return;
+        }
         fn clamp_relative(value: usize, reference: usize, dimension: usize) -> usize {
             value.clamp(reference.saturating_sub(dimension - 1), reference)
         }
@@ -139,7 +401,7 @@ impl TextWindow {
                 };
                 Selection::Range { start, end }
             }
-            Selection::Range { .. } => {
+            Selection::Range { .. } | Selection::Block { .. } => {
                 // Maybe eventually I'll prevent this case, but for now let's just reset.
                 Selection::Normal
             }
@@ -179,33 +441,76 @@ impl TextWindow {
     }
 
     pub fn replace(&mut self, replace: Replace) {
-        // Avoid editing the frozen section of the buffer.
-        let undo = self.do_replace(replace);
-        rotate_queue(&mut self.undos, undo, NUM_UNDOS);
-        self.redos.clear(); // No longer valid!
+        self.transaction(|window| {
+            let undo = window.do_replace(replace);
+            window
+                .transaction
+                .as_mut()
+                .expect("replacing within a transaction")
+                .record(undo, &window.buffer);
+        });
     }
 
-    pub fn undo(&mut self) {
-        if let Some(undo) = self.undos.pop_back() {
-            self.scroll_into_view(undo.from);
-            let redo = self.do_replace(undo);
-            self.redos.push(redo);
+    /// Run some edits as a single unit, so they're undone and redone together.
+    /// Nested transactions are merged into the outermost one.
+    pub fn transaction<A>(&mut self, edits: impl FnOnce(&mut Self) -> A) -> A {
+        if self.transaction.is_some() {
+            return edits(self);
         }
+        self.transaction = Some(Transaction::default());
+        let result = edits(self);
+        let transaction = self.transaction.take().unwrap_or_default();
+        if !transaction.undos.is_empty() {
+            let summary = transaction.summary();
+            self.undo_tree
+                .push(transaction.undos, summary, self.undo_limit);
+        }
+        result
     }
 
-    pub fn redo(&mut self) {
-        if let Some(redo) = self.redos.pop() {
-            self.scroll_into_view(redo.from);
-            let undo = self.do_replace(redo);
-            rotate_queue(&mut self.undos, undo, NUM_UNDOS);
+    /// Apply a group of replaces in reverse order, returning the group that reverts them.
+    fn replace_group(&mut self, group: Vec<Replace>) -> Vec<Replace> {
+        let mut reverted = Vec::with_capacity(group.len());
+        for replace in group.into_iter().rev() {
+            self.scroll_into_view(replace.from);
+            reverted.push(self.do_replace(replace));
         }
+        reverted
+    }
+
+    pub fn undo(&mut self) {
+        let mut undo_tree = mem::take(&mut self.undo_tree);
+        undo_tree.undo(|group| self.replace_group(group));
+        self.undo_tree = undo_tree;
     }
 
-    fn relative(&self, coord: Coord) -> Coord {
-        (self.origin.0 + coord.0, self.origin.1 + coord.1)
+    pub fn redo(&mut self) {
+        let mut undo_tree = mem::take(&mut self.undo_tree);
+        undo_tree.redo(|group| self.replace_group(group));
+        self.undo_tree = undo_tree;
+    }
+
+    /// Restore the buffer to an arbitrary state from the undo tree.
+    pub fn jump_to(&mut self, state: usize) {
+        let mut undo_tree = mem::take(&mut self.undo_tree);
+        undo_tree.jump(state, |group| self.replace_group(group));
+        self.undo_tree = undo_tree;
+    }
+
+    /// The buffer coordinate under a cell of the grid.
+    fn relative(&self, (row, col): Coord) -> Coord {
+        let mut start = self.origin;
+        for _ in 0..row {
+            start = self.next_screen_row(start);
+        }
+        (start.0, start.1 + col)
     }
 
     pub fn erase(&mut self, ink: Ink) {
+        self.transaction(|window| window.do_erase(ink));
+    }
+
+    fn do_erase(&mut self, ink: Ink) {
         let width = self.grid_metrics.width as f32;
         let height = self.grid_metrics.height as f32;
         let ink = ink.resample(width / 2.0);
@@ -253,15 +558,15 @@ impl TextWindow {
 
     fn find_token(&mut self, start: Coord, end: Coord, forward: bool) {
         let query = self.buffer.copy(start, end);
-        let line: &[char] = &query.contents[0];
+        let line: &[char] = query.line(0).expect("buffers always have a line");
 
         fn find_in(
-            contents: &Vec<Vec<char>>,
+            buffer: &TextBuffer,
             query: &[char],
             rows: impl Iterator<Item = usize>,
         ) -> Option<(usize, usize)> {
             rows.filter_map(|row| {
-                let row_data = &contents[row];
+                let row_data = buffer.line(row)?;
                 row_data
                     .windows(query.len())
                     .enumerate()
@@ -272,13 +577,9 @@ impl TextWindow {
         }
 
         let result = if forward {
-            find_in(
-                &self.buffer.contents,
-                line,
-                (start.0 + 1)..self.buffer.contents.len(),
-            )
+            find_in(&self.buffer, line, (start.0 + 1)..self.buffer.line_count())
         } else {
-            find_in(&self.buffer.contents, line, (0..start.0).rev())
+            find_in(&self.buffer, line, (0..start.0).rev())
         };
 
         if let Some(new_start) = result {
@@ -293,15 +594,220 @@ impl TextWindow {
         }
     }
 
+    /// Handle a big glyph while there's a block selection. Edits apply to the same columns of
+    /// every row in the block, which is handy for tables and other aligned text.
+    fn block_command(
+        &mut self,
+        command: Option<char>,
+        start: Coord,
+        end: Coord,
+        text_stuff: &mut TextStuff,
+    ) {
+        let (top, bottom, left, right) = block_bounds(start, end);
+        match command {
+            Some('X') | Some('C') => {
+                text_stuff.clipboard = Some(Clipboard {
+                    content: self.copy_block(start, end),
+                    block: true,
+                });
+                if command == Some('X') {
+                    self.remove_block(start, end);
+                }
+            }
+            Some('V') => {
+                if let Some(clipboard) = &text_stuff.clipboard {
+                    self.remove_block(start, end);
+                    self.paste_block((top, left), &clipboard.content, bottom - top + 1);
+                }
+            }
+            Some('S') | Some('>') => {
+                for row in top..=bottom {
+                    self.replace(Replace::splice(
+                        (row, left),
+                        TextBuffer::padding((0, right - left)),
+                    ));
+                }
+            }
+            Some('<') => {
+                self.remove_block(start, end);
+            }
+            Some('B') => {
+                // Back to an ordinary range.
+                if let Selection::Block { start, end } = mem::take(&mut self.selection) {
+                    self.selection = Selection::Range { start, end };
+                }
+                return;
+            }
+            _ => return,
+        }
+        self.selection = Selection::Normal;
+    }
+
+    /// The contents of a block, padded with spaces so every row is the full width.
+    fn copy_block(&self, start: Coord, end: Coord) -> TextBuffer {
+        let (top, bottom, left, right) = block_bounds(start, end);
+        let rows: Vec<String> = (top..=bottom)
+            .map(|row| {
+                let line = self.buffer.line(row).unwrap_or(&[]);
+                let text: String = line.iter().skip(left).take(right - left).collect();
+                format!("{text:width$}", width = right - left)
+            })
+            .collect();
+        TextBuffer::from_string(&rows.join("\n"))
+    }
+
+    fn remove_block(&mut self, start: Coord, end: Coord) {
+        let (top, bottom, left, right) = block_bounds(start, end);
+        for row in top..=bottom {
+            let len = self.line_len(row);
+            if left < len {
+                self.replace(Replace::remove((row, left), (row, right.min(len))));
+            }
+        }
+    }
+
+    /// Insert each line of the content on its own row, starting from the same column. If the
+    /// content is a single line, it's repeated on `rows` rows.
+    fn paste_block(&mut self, (top, left): Coord, content: &TextBuffer, rows: usize) {
+        let lines: Vec<String> = content.lines().map(|l| l.iter().collect()).collect();
+        let rows = if lines.len() == 1 { rows } else { lines.len() };
+        for i in 0..rows {
+            let line = &lines[i.min(lines.len() - 1)];
+            let row = top + i;
+            // Don't leave trailing whitespace on rows that stop before the block.
+            let line = if self.line_len(row) <= left {
+                line.trim_end()
+            } else {
+                line.as_str()
+            };
+            if !line.is_empty() {
+                self.replace(Replace::splice((row, left), TextBuffer::from_string(line)));
+            }
+        }
+    }
+
+    /// Apply some ink to the window. All the edits from a single ink are undone together.
     pub fn ink_row(&mut self, ink_type: InkType, text_stuff: &mut TextStuff) {
+        self.transaction(|window| window.do_ink_row(ink_type, text_stuff));
+    }
+
+    /// The cells written by the most recent ink, along with the other chars they might have been.
+    /// Cells that have been edited since are left out.
+    pub fn alternatives(&self) -> Vec<(Coord, char, Vec<char>)> {
+        let skip = self
+            .tentative_recognitions
+            .len()
+            .saturating_sub(self.last_recognized);
+        self.tentative_recognitions
+            .iter()
+            .skip(skip)
+            .filter(|r| self.char_at(r.coord) == Some(r.recognized_as))
+            .map(|r| {
+                let others = r
+                    .candidates
+                    .iter()
+                    .copied()
+                    .filter(|c| *c != r.recognized_as)
+                    .collect();
+                (r.coord, r.recognized_as, others)
+            })
+            .collect()
+    }
+
+    /// Replace a recently-recognized char with one of its alternatives.
+    pub fn correct(&mut self, coord: Coord, c: char, text_stuff: &mut TextStuff) {
+        let current = self.char_at(coord);
+        // Confirming the current guess teaches us nothing new.
+        if current == Some(c) {
+            return;
+        }
+        let recognition = match self
+            .tentative_recognitions
+            .iter_mut()
+            .find(|r| r.coord == coord && Some(r.recognized_as) == current)
+        {
+            Some(r) => r,
+            None => return,
+        };
+        // If this gets overwritten later, it's the corrected char that was wrong.
+        recognition.recognized_as = c;
+        let ink = recognition.ink.clone();
+        self.transaction(|window| window.replace(Replace::write(coord, c)));
+        text_stuff.on_correction(ink, &self.grid_metrics, c);
+    }
+
+    /// Learn from a recognition that's dropped out of the tentative queue. By now, the cell has
+    /// settled on what all its ink was meant to be.
+    fn settle(&self, r: Recognition, text_stuff: &mut TextStuff) {
+        dbg!(r.recognized_as, r.overwrites.len());
+        // If the cell's been changed some other way since, like scratched out or moved, we can't
+        // be sure what it was meant to be, so it's not worth recording.
+        let still_there = self.char_at(r.coord) == Some(r.recognized_as);
+        let record = still_there && self.record_samples;
+        let height = self.grid_metrics.height;
+        if record {
+            text_stuff.record_sample(&r.ink, r.guessed, r.recognized_as, height);
+        }
+        for (ink, guessed) in r.overwrites {
+            if record {
+                text_stuff.record_sample(&ink, guessed, r.recognized_as, height);
+            }
+            let points = ink_to_points(&ink, &self.grid_metrics);
+            text_stuff.on_overwrite(ink, points, r.recognized_as);
+        }
+    }
+
+    /// Settle every recent recognition at once; eg. when the window is closed, since otherwise
+    /// they'd be lost.
+    pub fn settle_all(&mut self, text_stuff: &mut TextStuff) {
+        for r in mem::take(&mut self.tentative_recognitions) {
+            self.settle(r, text_stuff);
+        }
+        self.last_recognized = 0;
+    }
+
+    fn char_at(&self, (row, col): Coord) -> Option<char> {
+        self.buffer.line(row)?.get(col).copied()
+    }
+
+    fn do_ink_row(&mut self, ink_type: InkType, text_stuff: &mut TextStuff) {
+        if !matches!(ink_type, InkType::Glyphs { .. }) {
+            self.last_recognized = 0;
+        }
         match ink_type {
             InkType::Scratch { at } => {
                 let coord = self.relative(at);
                 self.replace(Replace::write(coord, ' '));
             }
             InkType::Glyphs { tokens } => {
-                // TODO: a little coalescing perhaps?
+                self.last_recognized = 0;
+                let mut written = vec![];
                 for (col, ink) in tokens {
+                    let coord = self.relative(col);
+                    let candidates = text_stuff.char_recognizer.candidates(
+                        &ink_to_points(&ink, &self.grid_metrics),
+                        NUM_ALTERNATIVES + 1,
+                        |c| self.allowed_chars.is_none_or(|allowed| allowed(c)),
+                    );
+                    if !candidates.is_empty() {
+                        written.push((coord, ink, candidates));
+                    }
+                }
+
+                // Each glyph is recognized on its own, so similar chars like `l` and `1` are
+                // easy to mix up. A language model can tell them apart by the company they keep.
+                let chosen: Vec<char> = match &text_stuff.language_model {
+                    Some(model) => {
+                        let cells: Vec<_> = written
+                            .iter()
+                            .map(|(coord, _, candidates)| (*coord, candidates.as_slice()))
+                            .collect();
+                        model.rescore(&cells, |coord| self.char_at(coord))
+                    }
+                    None => written.iter().map(|(_, _, c)| c[0].0).collect(),
+                };
+
+                for ((coord, ink, candidates), c) in written.into_iter().zip(chosen) {
                     // So, this is a slightly awkward little dance. The key observation is that
                     // if the system mispredicts a character, the user will almost always try
                     // and overwrite the bad guess again to "fix up" the text; and when that
@@ -321,46 +827,40 @@ impl TextWindow {
                     // and track how well the candidates do. Candidates that are reliable get
                     // promoted to the main template list. We presumably will still get this
                     // wrong, but at least users can prune bad ones from there if needed.
-                    let coord = self.relative(col);
-                    if let Some(c) = text_stuff
-                        .char_recognizer
-                        .best_match(&ink_to_points(&ink, &self.grid_metrics), f32::MAX)
+                    let candidates = candidates.into_iter().map(|(c, _)| c).collect();
+                    let overwrites = if let Some(index) = self
+                        .tentative_recognitions
+                        .iter()
+                        .position(|r| r.coord == coord)
                     {
-                        let overwrites = if let Some(index) = self
+                        let mut prev = self
                             .tentative_recognitions
-                            .iter()
-                            .position(|r| r.coord == coord)
-                        {
-                            let mut prev = self
-                                .tentative_recognitions
-                                .remove(index)
-                                .expect("removing just-discovered match");
-                            prev.overwrites.push(prev.ink);
-                            prev.overwrites
-                        } else {
-                            vec![]
-                        };
+                            .remove(index)
+                            .expect("removing just-discovered match");
+                        prev.overwrites.push((prev.ink, prev.guessed));
+                        prev.overwrites
+                    } else {
+                        vec![]
+                    };
 
-                        let recon = Recognition {
-                            coord,
-                            ink,
-                            recognized_as: c,
-                            overwrites,
-                        };
+                    let recon = Recognition {
+                        coord,
+                        ink,
+                        recognized_as: c,
+                        guessed: c,
+                        candidates,
+                        overwrites,
+                    };
 
-                        self.replace(Replace::write(coord, c));
-
-                        if let Some(r) = rotate_queue(
-                            &mut self.tentative_recognitions,
-                            recon,
-                            NUM_RECENT_RECOGNITIONS,
-                        ) {
-                            dbg!(r.recognized_as, r.overwrites.len());
-                            for ink in r.overwrites {
-                                let points = ink_to_points(&ink, &self.grid_metrics);
-                                text_stuff.on_overwrite(ink, points, r.recognized_as);
-                            }
-                        }
+                    self.replace(Replace::write(coord, c));
+                    self.last_recognized += 1;
+
+                    if let Some(r) = rotate_queue(
+                        &mut self.tentative_recognitions,
+                        recon,
+                        NUM_RECENT_RECOGNITIONS,
+                    ) {
+                        self.settle(r, text_stuff);
                     }
                 }
             }
@@ -383,27 +883,53 @@ impl TextWindow {
                     Selection::Normal => unreachable!("checked in matches! above."),
                     Selection::Single { carat } => (carat.coord, carat.coord),
                     Selection::Range { start, end } => (start.coord, end.coord),
+                    Selection::Block { start, end } => {
+                        let (start, end) = (start.coord, end.coord);
+                        self.block_command(best_match, start, end, text_stuff);
+                        return;
+                    }
                 };
                 match best_match {
                     Some('X') if start != end => {
-                        text_stuff.clipboard = Some(self.buffer.copy(start, end));
+                        text_stuff.clipboard = Some(Clipboard {
+                            content: self.buffer.copy(start, end),
+                            block: false,
+                        });
                         self.replace(Replace::remove(start, end));
                         self.selection = Selection::Normal;
                     }
                     Some('C') if start != end => {
-                        text_stuff.clipboard = Some(self.buffer.copy(start, end));
+                        text_stuff.clipboard = Some(Clipboard {
+                            content: self.buffer.copy(start, end),
+                            block: false,
+                        });
                         self.selection = Selection::Normal;
                     }
                     Some('V') => {
-                        if let Some(buffer) = &text_stuff.clipboard {
-                            self.replace(Replace {
-                                from: start,
-                                until: end,
-                                content: buffer.clone(),
-                            });
+                        match &text_stuff.clipboard {
+                            Some(Clipboard {
+                                content,
+                                block: true,
+                            }) => {
+                                self.replace(Replace::remove(start, end));
+                                self.paste_block(start, content, 1);
+                            }
+                            Some(Clipboard { content, .. }) => {
+                                self.replace(Replace {
+                                    from: start,
+                                    until: end,
+                                    content: content.clone(),
+                                });
+                            }
+                            None => {}
                         }
                         self.selection = Selection::Normal;
                     }
+                    Some('B') if start != end => {
+                        if let Selection::Range { start, end } = mem::take(&mut self.selection) {
+                            self.selection = Selection::Block { start, end };
+                        }
+                    }
                     Some('S') | Some('>') => {
                         self.replace(Replace::splice(
                             start,
@@ -422,7 +948,7 @@ impl TextWindow {
                         } else {
                             end
                         };
-                        let remaining_width = self.dimensions.1 - start.1;
+                        let remaining_width = self.dimensions.1.saturating_sub(start.1).max(1);
                         let prefix = self.buffer.copy(line_start, start).content_string();
                         let remainder = self.buffer.copy(start, end).content_string();
                         let remainder = remainder.replace(&format!("\n{}", prefix), " ");
@@ -477,7 +1003,7 @@ impl Widget for TextWindow {
     }
 
     fn render(&self, view: View<Self::Message>) {
-        let (row_origin, col_origin) = self.origin;
+        let screen_rows = self.screen_rows();
         draw_grid(
             view,
             &self.grid_metrics,
@@ -487,8 +1013,8 @@ impl Widget for TextWindow {
                 view.handlers().on_erase(TextMessage::Erase);
             },
             |row_offset, col_offset, mut view| {
-                let row = row_origin + row_offset;
-                let col = col_origin + col_offset;
+                let (row, line_start) = screen_rows[row_offset];
+                let col = line_start + col_offset;
                 let coord = (row, col);
 
                 let (underline, draw_guidelines) = match &self.selection {
@@ -509,20 +1035,33 @@ impl Widget for TextWindow {
                         let in_selection = coord >= start.coord && coord < end.coord;
                         (in_selection, false)
                     }
+                    Selection::Block { start, end } => {
+                        if coord == start.coord {
+                            view.annotate(&start.ink);
+                        }
+                        if coord == end.coord {
+                            view.annotate(&end.ink);
+                        }
+                        let (top, bottom, left, right) = block_bounds(start.coord, end.coord);
+                        let in_selection =
+                            (top..=bottom).contains(&row) && (left..right).contains(&col);
+                        (in_selection, false)
+                    }
                 };
 
-                let line = self.buffer.contents.get(row);
+                let line = self.buffer.line(row);
                 let char = line
                     .map(|l| match col.cmp(&l.len()) {
                         Ordering::Less => {
                             let ch = l[col];
                             match ch {
                                 '\t' => Some(('⇨', 80)),
+                                other if raw_byte(other).is_some() => Some(('�', 80)),
                                 other => Some((other, 230)),
                             }
                         }
                         Ordering::Equal => {
-                            let char = if row + 1 == self.buffer.contents.len() {
+                            let char = if row + 1 == self.buffer.line_count() {
                                 '⌧'
                             } else {
                                 '⏎'
@@ -544,3 +1083,63 @@ impl Widget for TextWindow {
         );
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(text: &str, dimensions: Coord) -> TextWindow {
+        let metrics = Metrics {
+            height: 40,
+            width: 20,
+            baseline: 30,
+        };
+        let mut window = TextWindow::new(
+            TextBuffer::from_string(text),
+            Rc::new(Atlas::new()),
+            metrics,
+            dimensions,
+        );
+        window.wrap = true;
+        window
+    }
+
+    #[test]
+    fn test_wrapped_rows() {
+        let mut text = window("abcdefghij\nxy\n", (3, 4));
+        assert_eq!(text.screen_rows(), [(0, 0), (0, 4), (0, 8)]);
+        assert_eq!(text.relative((1, 2)), (0, 6));
+        assert_eq!(text.onscreen((0, 9)), Some((2, 1)));
+        assert_eq!(text.onscreen((1, 1)), Some((3, 1)));
+
+        text.scroll_into_view((1, 1));
+        assert_eq!(text.origin, (0, 4));
+        assert_eq!(text.onscreen((0, 2)), None);
+        assert_eq!(text.relative((2, 1)), (1, 1));
+
+        text.page_relative((-1, 0));
+        assert_eq!(text.origin, (0, 0));
+        text.toggle_wrap();
+        assert_eq!(text.relative((1, 2)), (1, 2));
+
+        // The end-of-line marker needs a cell too, so a full row spills over.
+        let text = window("abcd\nx", (3, 4));
+        assert_eq!(text.screen_rows(), [(0, 0), (0, 4), (1, 0)]);
+    }
+
+    #[test]
+    fn test_block_edits() {
+        let mut text = window("name  age\nbo    31\nalexandra 7\n", (4, 20));
+        let copied = text.copy_block((2, 6), (0, 0));
+        assert_eq!(copied.content_string(), "name  \nbo    \nalexan");
+
+        text.remove_block((0, 6), (2, 9));
+        assert_eq!(text.buffer.content_string(), "name  \nbo    \nalexan 7\n");
+
+        text.paste_block((0, 6), &TextBuffer::from_string("| "), 3);
+        assert_eq!(
+            text.buffer.content_string(),
+            "name  |\nbo    |\nalexan|  7\n"
+        );
+    }
+}
@@ -0,0 +1,272 @@
+use crate::util::now;
+use crate::Replace;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::mem;
+
+#[derive(Clone, Serialize, Deserialize)]
+struct Node {
+    parent: Option<usize>,
+    /// The edits that cross the edge between this node and its parent. If the current state is
+    /// this node or one of its descendants, these revert to the parent state; otherwise, they
+    /// reapply this node's edit. Crossing the edge swaps one for the other.
+    edits: Vec<Replace>,
+    /// The child we'd return to on redo: whichever was visited most recently.
+    redo_child: Option<usize>,
+    /// When the edit was made, in seconds since the epoch.
+    time: u64,
+    summary: String,
+}
+
+/// A past state of the buffer, as listed in the history tab.
+pub struct HistoryEntry<'a> {
+    pub id: usize,
+    pub time: u64,
+    pub summary: &'a str,
+    pub current: bool,
+}
+
+/// The undo history of a buffer. Unlike a simple undo stack, making an edit after an undo
+/// doesn't throw away the undone edits: they stay around as another branch of the tree.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct UndoTree {
+    /// Keyed by id; ids are allocated in increasing order, so lower ids are older.
+    nodes: BTreeMap<usize, Node>,
+    root: usize,
+    current: usize,
+    next_id: usize,
+}
+
+impl Default for UndoTree {
+    fn default() -> Self {
+        let mut nodes = BTreeMap::new();
+        nodes.insert(
+            0,
+            Node {
+                parent: None,
+                edits: vec![],
+                redo_child: None,
+                time: now(),
+                summary: "opened".to_string(),
+            },
+        );
+        UndoTree {
+            nodes,
+            root: 0,
+            current: 0,
+            next_id: 1,
+        }
+    }
+}
+
+impl UndoTree {
+    pub fn clear(&mut self) {
+        *self = UndoTree::default();
+    }
+
+    /// Identifies the current state of the buffer.
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn can_undo(&self) -> bool {
+        self.nodes[&self.current].parent.is_some()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.nodes[&self.current].redo_child.is_some()
+    }
+
+    /// Record a new edit on top of the current state, given the replaces that would revert it.
+    pub fn push(&mut self, undos: Vec<Replace>, summary: String, limit: usize) {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.nodes.insert(
+            id,
+            Node {
+                parent: Some(self.current),
+                edits: undos,
+                redo_child: None,
+                time: now(),
+                summary,
+            },
+        );
+        self.nodes
+            .get_mut(&self.current)
+            .expect("current node exists")
+            .redo_child = Some(id);
+        self.current = id;
+        self.prune(limit);
+    }
+
+    /// Cross the edge between a node and its parent, in whichever direction applies.
+    fn cross(&mut self, id: usize, apply: &mut impl FnMut(Vec<Replace>) -> Vec<Replace>) {
+        let node = self.nodes.get_mut(&id).expect("crossing to a known node");
+        let edits = mem::take(&mut node.edits);
+        node.edits = apply(edits);
+    }
+
+    pub fn undo(&mut self, mut apply: impl FnMut(Vec<Replace>) -> Vec<Replace>) {
+        if let Some(parent) = self.nodes[&self.current].parent {
+            let child = self.current;
+            self.cross(child, &mut apply);
+            self.current = parent;
+            self.nodes
+                .get_mut(&parent)
+                .expect("parent exists")
+                .redo_child = Some(child);
+        }
+    }
+
+    pub fn redo(&mut self, mut apply: impl FnMut(Vec<Replace>) -> Vec<Replace>) {
+        if let Some(child) = self.nodes[&self.current].redo_child {
+            self.cross(child, &mut apply);
+            self.current = child;
+        }
+    }
+
+    fn ancestors(&self, mut id: usize) -> Vec<usize> {
+        let mut result = vec![id];
+        while let Some(parent) = self.nodes[&id].parent {
+            result.push(parent);
+            id = parent;
+        }
+        result
+    }
+
+    /// Move to an arbitrary state in the tree: undoing back to the common ancestor of the current
+    /// and target states, then redoing forward to the target.
+    pub fn jump(&mut self, target: usize, mut apply: impl FnMut(Vec<Replace>) -> Vec<Replace>) {
+        if !self.nodes.contains_key(&target) {
+            return;
+        }
+        let target_path = self.ancestors(target);
+        while !target_path.contains(&self.current) {
+            self.undo(&mut apply);
+        }
+        let common = target_path
+            .iter()
+            .position(|id| *id == self.current)
+            .expect("current is on the target path");
+        for child in target_path[..common].iter().rev() {
+            self.nodes
+                .get_mut(&self.current)
+                .expect("current node exists")
+                .redo_child = Some(*child);
+            self.redo(&mut apply);
+        }
+    }
+
+    /// Drop the oldest nodes until there are at most `limit` edits in the tree. We only drop
+    /// nodes from the outside of the tree: leaves, or the root if it has a single child.
+    pub fn prune(&mut self, limit: usize) {
+        while self.nodes.len() > limit + 1 {
+            let mut child_counts: BTreeMap<usize, usize> = BTreeMap::new();
+            for node in self.nodes.values() {
+                if let Some(parent) = node.parent {
+                    *child_counts.entry(parent).or_default() += 1;
+                }
+            }
+            let removable = |id: &usize| {
+                let children = child_counts.get(id).copied().unwrap_or(0);
+                *id != self.current
+                    && if *id == self.root {
+                        children == 1
+                    } else {
+                        children == 0
+                    }
+            };
+            let oldest = match self.nodes.keys().copied().find(removable) {
+                Some(id) => id,
+                None => return,
+            };
+
+            let removed = self.nodes.remove(&oldest).expect("removing a known node");
+            match removed.parent {
+                Some(parent) => {
+                    let parent = self.nodes.get_mut(&parent).expect("parent exists");
+                    if parent.redo_child == Some(oldest) {
+                        parent.redo_child = None;
+                    }
+                }
+                None => {
+                    let child = removed.redo_child.expect("root has a single child");
+                    let new_root = self.nodes.get_mut(&child).expect("child exists");
+                    new_root.parent = None;
+                    new_root.edits.clear();
+                    self.root = child;
+                }
+            }
+        }
+    }
+
+    /// All the states in the tree, newest first.
+    pub fn entries(&self) -> impl Iterator<Item = HistoryEntry<'_>> + '_ {
+        self.nodes.iter().rev().map(|(id, node)| HistoryEntry {
+            id: *id,
+            time: node.time,
+            summary: &node.summary,
+            current: *id == self.current,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TextBuffer;
+
+    fn apply_to(buffer: &mut TextBuffer) -> impl FnMut(Vec<Replace>) -> Vec<Replace> + '_ {
+        |group| {
+            group
+                .into_iter()
+                .rev()
+                .map(|replace| buffer.replace(replace))
+                .collect()
+        }
+    }
+
+    fn edit(tree: &mut UndoTree, buffer: &mut TextBuffer, text: &str) {
+        let end = buffer.end();
+        let undo = buffer.replace(Replace::splice(end, TextBuffer::from_string(text)));
+        tree.push(vec![undo], text.to_string(), 10);
+    }
+
+    #[test]
+    fn test_branches() {
+        let mut tree = UndoTree::default();
+        let mut buffer = TextBuffer::empty();
+        edit(&mut tree, &mut buffer, "a");
+        edit(&mut tree, &mut buffer, "b");
+        let ab = tree.current;
+        tree.undo(apply_to(&mut buffer));
+        edit(&mut tree, &mut buffer, "c");
+        assert_eq!(buffer.content_string(), "ac");
+        assert!(!tree.can_redo());
+
+        // The abandoned branch is still reachable.
+        tree.jump(ab, apply_to(&mut buffer));
+        assert_eq!(buffer.content_string(), "ab");
+        tree.undo(apply_to(&mut buffer));
+        tree.undo(apply_to(&mut buffer));
+        assert_eq!(buffer.content_string(), "");
+        tree.redo(apply_to(&mut buffer));
+        tree.redo(apply_to(&mut buffer));
+        assert_eq!(buffer.content_string(), "ab");
+    }
+
+    #[test]
+    fn test_prune() {
+        let mut tree = UndoTree::default();
+        let mut buffer = TextBuffer::empty();
+        for text in ["a", "b", "c", "d"] {
+            edit(&mut tree, &mut buffer, text);
+        }
+        tree.prune(2);
+        assert_eq!(tree.entries().count(), 3);
+        while tree.can_undo() {
+            tree.undo(apply_to(&mut buffer));
+        }
+        assert_eq!(buffer.content_string(), "ab");
+    }
+}
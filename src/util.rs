@@ -1,4 +1,10 @@
 use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::Write;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn rotate_queue<T>(queue: &mut VecDeque<T>, value: T, capacity: usize) -> Option<T> {
     let popped = if queue.len() == capacity {
@@ -11,3 +17,118 @@ pub fn rotate_queue<T>(queue: &mut VecDeque<T>, value: T, capacity: usize) -> Op
 
     popped
 }
+
+/// A fast, stable hash of some file contents. (Unlike the std hashers, this won't change
+/// between releases, so it's safe to persist.)
+pub fn content_hash(bytes: &[u8]) -> u64 {
+    // 64-bit FNV-1a.
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
+/// Write a file by writing a temporary file alongside it and renaming it into place, so a crash
+/// leaves either the old contents or the new ones, but never a partial write. The file is only
+/// readable by its owner.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    write_atomic_with(path, contents, 0o600, |_| Ok(()))
+}
+
+/// Like `write_atomic`, but the temporary file is created with the given mode (less the umask),
+/// and `prepare` is called on it before anything is written; for example, to set its owner.
+/// That way the contents are never visible to anyone the finished file wouldn't be.
+pub fn write_atomic_with(
+    path: &Path,
+    contents: &[u8],
+    mode: u32,
+    prepare: impl FnOnce(&File) -> io::Result<()>,
+) -> io::Result<()> {
+    let mut temp_name = path.file_name().unwrap_or_default().to_os_string();
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    // Never follow or reuse whatever's already at the temporary path; it may be left over from a
+    // crash, or planted there.
+    let create = || {
+        OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temp_path)
+    };
+    let file = match create() {
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            std::fs::remove_file(&temp_path)?;
+            create()
+        }
+        other => other,
+    };
+    let result = file.and_then(|mut file| {
+        prepare(&file)?;
+        file.write_all(contents)?;
+        file.sync_all()
+    });
+    let result = result.and_then(|_| std::fs::rename(&temp_path, path));
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp_path);
+    }
+    result
+}
+
+/// The current time, in seconds since the epoch.
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Describe how long ago a timestamp was, in seconds since the epoch, in a compact way.
+pub fn format_age(then: u64, now: u64) -> String {
+    let secs = now.saturating_sub(then);
+    match secs {
+        0..=59 => format!("{secs}s ago"),
+        60..=3599 => format!("{}m ago", secs / 60),
+        3600..=86399 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+/// Describe a number of bytes in a compact way, like `ls -h`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["K", "M", "G", "T"];
+    if bytes < 1024 {
+        return format!("{bytes}B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    for unit in UNITS {
+        if size < 1024.0 || unit == "T" {
+            return if size < 10.0 {
+                format!("{size:.1}{unit}")
+            } else {
+                format!("{size:.0}{unit}")
+            };
+        }
+        size /= 1024.0;
+    }
+    unreachable!("the last unit always returns")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(0), "0B");
+        assert_eq!(format_size(1023), "1023B");
+        assert_eq!(format_size(1024), "1.0K");
+        assert_eq!(format_size(1536), "1.5K");
+        assert_eq!(format_size(20 * 1024), "20K");
+        assert_eq!(format_size(3 << 20), "3.0M");
+        assert_eq!(format_size(5 << 30), "5.0G");
+        assert_eq!(format_size(2048 << 40), "2048T");
+    }
+}
@@ -18,6 +18,25 @@ impl Fragment for Underline {
     }
 }
 
+/// A piece of a scrollbar: a thick bar if that part of the file is onscreen, or a thin line.
+#[derive(Hash)]
+pub struct ScrollSegment {
+    pub onscreen: bool,
+}
+
+impl Fragment for ScrollSegment {
+    fn draw(&self, canvas: &mut Canvas) {
+        let size = canvas.bounds().size();
+        let (width, shade) = if self.onscreen { (12, 100) } else { (2, 200) };
+        let left = (size.x - width) / 2;
+        for y in 2..(size.y - 2) {
+            for x in left.max(0)..(left + width).min(size.x) {
+                canvas.write(x, y, color::GRAY(shade));
+            }
+        }
+    }
+}
+
 pub struct Button<T: Widget> {
     widget: T,
     on_tap: Option<T::Message>,